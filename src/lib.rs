@@ -1,513 +1,8401 @@
 use std::{
+    borrow::Cow,
+    collections::{HashMap, VecDeque},
     error::Error,
+    fmt::{self, Display},
     fs,
-    io::{self, BufRead, BufReader, BufWriter, Write},
-    writeln,
+    io::{self, BufRead, BufReader, BufWriter, IsTerminal, Read, Write},
+    iter,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    vec, writeln,
 };
 
+#[cfg(feature = "base64")]
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
 pub enum InputSource {
     StdIn,
     File(String),
+    #[cfg(feature = "ureq")]
+    Url(String),
+    // A regular file inside a tar archive, named `archive::member`
+    #[cfg(feature = "tar")]
+    TarMember {
+        archive: String,
+        member: String,
+    },
+    // Already open, and unnamed like standard input
+    Reader(Box<dyn BufRead>),
+    // Already in memory, and unnamed like standard input
+    Bytes(Vec<u8>),
+    // An open file descriptor, like a pipe from process substitution, read
+    // without going through `/dev/fd`. Reading it takes ownership of it and
+    // closes it once done, so it must be open and not owned by anything
+    // else.
+    #[cfg(unix)]
+    Fd(i32),
+    // The text on the clipboard, named `clipboard`
+    #[cfg(feature = "arboard")]
+    Clipboard(Box<dyn ClipboardProvider>),
+}
+
+// Where clipboard text comes from, the system's clipboard for
+// `InputSource::clipboard()`
+#[cfg(feature = "arboard")]
+pub trait ClipboardProvider {
+    fn get_text(&mut self) -> Result<String, String>;
+}
+
+#[cfg(feature = "arboard")]
+struct SystemClipboard;
+
+#[cfg(feature = "arboard")]
+impl ClipboardProvider for SystemClipboard {
+    fn get_text(&mut self) -> Result<String, String> {
+        arboard::Clipboard::new()
+            .and_then(|mut clipboard| clipboard.get_text())
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl fmt::Debug for InputSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use InputSource::*;
+        match self {
+            StdIn => write!(f, "StdIn"),
+            File(path) => f.debug_tuple("File").field(path).finish(),
+            #[cfg(feature = "ureq")]
+            Url(url) => f.debug_tuple("Url").field(url).finish(),
+            #[cfg(feature = "tar")]
+            TarMember { archive, member } => f
+                .debug_struct("TarMember")
+                .field("archive", archive)
+                .field("member", member)
+                .finish(),
+            Reader(_) => write!(f, "Reader(..)"),
+            Bytes(bytes) => write!(f, "Bytes({} bytes)", bytes.len()),
+            #[cfg(unix)]
+            Fd(fd) => f.debug_tuple("Fd").field(fd).finish(),
+            #[cfg(feature = "arboard")]
+            Clipboard(_) => write!(f, "Clipboard(..)"),
+        }
+    }
+}
+
+// Readers can't be compared, so they're never equal to anything
+impl PartialEq for InputSource {
+    fn eq(&self, other: &Self) -> bool {
+        use InputSource::*;
+        match (self, other) {
+            (StdIn, StdIn) => true,
+            (File(a), File(b)) => a == b,
+            (Bytes(a), Bytes(b)) => a == b,
+            #[cfg(feature = "ureq")]
+            (Url(a), Url(b)) => a == b,
+            #[cfg(feature = "tar")]
+            (
+                TarMember { archive, member },
+                TarMember {
+                    archive: other_archive,
+                    member: other_member,
+                },
+            ) => archive == other_archive && member == other_member,
+            #[cfg(unix)]
+            (Fd(a), Fd(b)) => a == b,
+            _ => false,
+        }
+    }
 }
 
 impl InputSource {
-    fn get_buf_read(self) -> Result<Box<dyn BufRead>, Box<dyn Error>> {
+    // The input a name on the command line or in a list of files refers to:
+    // `-` for standard input, a URL, a member of a tar archive as in
+    // `archive.tar::path/inside`, or a file's path
+    pub fn from_name(name: impl Into<String>) -> Self {
+        let name = name.into();
+        #[cfg(feature = "tar")]
+        if let Some((archive, member)) = name.split_once(".tar::") {
+            return InputSource::TarMember {
+                archive: format!("{archive}.tar"),
+                member: member.to_string(),
+            };
+        }
+        match name.as_str() {
+            "-" => InputSource::StdIn,
+            #[cfg(feature = "ureq")]
+            _ if name.starts_with("http://") || name.starts_with("https://") => {
+                InputSource::Url(name)
+            }
+            _ => InputSource::File(name),
+        }
+    }
+
+    // Each regular file in the tar archive at `archive`, in the order they
+    // were archived
+    #[cfg(feature = "tar")]
+    pub fn tar_members(archive: &str) -> io::Result<Vec<InputSource>> {
+        let files = tar_files(archive)?;
+        Ok(files
+            .into_iter()
+            .map(|(member, ..)| InputSource::TarMember {
+                archive: archive.to_string(),
+                member,
+            })
+            .collect())
+    }
+
+    // The text on the system's clipboard, read when the input is opened
+    #[cfg(feature = "arboard")]
+    pub fn clipboard() -> Self {
+        InputSource::Clipboard(Box::new(SystemClipboard))
+    }
+
+    // Each regular file under the directory `dir`, however deep, sorted by
+    // name within each directory. Symlinks aren't followed.
+    pub fn dir_files(dir: impl AsRef<Path>) -> io::Result<Vec<InputSource>> {
+        let mut files = Vec::new();
+        walk_dir(dir.as_ref(), &mut files)?;
+        Ok(files)
+    }
+
+    fn get_buf_read(self) -> OpenedInput {
         use InputSource::*;
         match self {
-            StdIn => Ok(Box::new(BufReader::new(io::stdin()))),
-            File(path) => Ok(Box::new(BufReader::new(
-                fs::File::open(&path).map_err(|e| format!("{path}: {e}"))?,
-            ))),
+            StdIn => Ok(Input::stdin(Box::new(BufReader::new(io::stdin())))),
+            Reader(reader) => Ok(Input::stdin(reader)),
+            Bytes(bytes) => Ok(Input::stdin(Box::new(io::Cursor::new(bytes)))),
+            #[cfg(unix)]
+            Fd(fd) => {
+                use std::os::fd::FromRawFd;
+
+                // SAFETY: the caller hands the descriptor over to us, as
+                // documented on `Fd`
+                let file = unsafe { fs::File::from_raw_fd(fd) };
+                Ok(Input::new(
+                    format!("/dev/fd/{fd}"),
+                    Box::new(BufReader::new(file)),
+                ))
+            }
+            #[cfg(feature = "arboard")]
+            Clipboard(mut provider) => {
+                let text = provider.get_text().map_err(|e| format!("clipboard: {e}"))?;
+                Ok(Input::new(
+                    "clipboard",
+                    Box::new(io::Cursor::new(text.into_bytes())),
+                ))
+            }
+            File(path) => {
+                let in_path = |e: io::Error| io::Error::new(e.kind(), format!("{path}: {e}"));
+                let file = fs::File::open(&path).map_err(in_path)?;
+                let reader =
+                    decompressed(&path, Box::new(BufReader::new(file))).map_err(in_path)?;
+                Ok(Input::new(path, reader))
+            }
+            #[cfg(feature = "tar")]
+            TarMember { archive, member } => {
+                use std::io::{Seek, SeekFrom};
+
+                let name = format!("{archive}::{member}");
+                let in_archive = |e: io::Error| io::Error::new(e.kind(), format!("{archive}: {e}"));
+                let files = tar_files(&archive).map_err(in_archive)?;
+                let Some(&(_, offset, size)) = files.iter().find(|(path, ..)| *path == member)
+                else {
+                    let message = format!("{name}: No such file or directory");
+                    return Err(io::Error::new(io::ErrorKind::NotFound, message).into());
+                };
+                // Read the member straight out of the archive
+                let mut file = fs::File::open(&archive).map_err(in_archive)?;
+                file.seek(SeekFrom::Start(offset)).map_err(in_archive)?;
+                Ok(Input::new(name, Box::new(BufReader::new(file.take(size)))))
+            }
+            #[cfg(feature = "ureq")]
+            Url(url) => {
+                // ureq reports 4xx/5xx as errors but we want anything other
+                // than 200 to be one
+                let response = match ureq::get(&url).call() {
+                    Ok(response) if response.status() == 200 => response,
+                    Ok(response) | Err(ureq::Error::Status(_, response)) => {
+                        return Err(format!(
+                            "{url}: {} {}",
+                            response.status(),
+                            response.status_text()
+                        )
+                        .into())
+                    }
+                    Err(e) => return Err(format!("{url}: {e}").into()),
+                };
+                Ok(Input::new(
+                    url,
+                    Box::new(BufReader::new(response.into_reader())),
+                ))
+            }
+        }
+    }
+}
+
+// The path, offset and size of each regular file in a tar archive
+#[cfg(feature = "tar")]
+fn tar_files(archive: &str) -> io::Result<Vec<(String, u64, u64)>> {
+    let mut archive = tar::Archive::new(fs::File::open(archive)?);
+    let mut files = Vec::new();
+    for entry in archive.entries_with_seek()? {
+        let entry = entry?;
+        if entry.header().entry_type().is_file() {
+            let path = entry.path()?.to_string_lossy().into_owned();
+            files.push((path, entry.raw_file_position(), entry.size()));
+        }
+    }
+    Ok(files)
+}
+
+// Adds the regular files under `dir` to `files`, for `dir_files()`
+fn walk_dir(dir: &Path, files: &mut Vec<InputSource>) -> io::Result<()> {
+    let in_dir = |e: io::Error| io::Error::new(e.kind(), format!("{}: {e}", dir.display()));
+    let mut entries = fs::read_dir(dir)
+        .and_then(|entries| entries.collect::<io::Result<Vec<_>>>())
+        .map_err(in_dir)?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let file_type = entry.file_type().map_err(in_dir)?;
+        if file_type.is_dir() {
+            walk_dir(&entry.path(), files)?;
+        } else if file_type.is_file() {
+            files.push(InputSource::File(
+                entry.path().to_string_lossy().into_owned(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "zstd")]
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+// What follows "BZh" and the block size at the start of a bzip2 stream: the
+// magic number of either its first block or the end of an empty stream
+#[cfg(feature = "bzip2")]
+const BZIP2_BLOCK_MAGICS: [[u8; 6]; 2] = [*b"1AY&SY", [0x17, 0x72, 0x45, 0x38, 0x50, 0x90]];
+#[cfg(feature = "xz2")]
+const XZ_MAGIC: [u8; 6] = [0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+// How much of the start of an input is looked at to tell if it's binary
+const BINARY_SNIFF_LEN: usize = 8 * 1024;
+
+// Up to the first `n` bytes `reader` has left, without consuming them, for
+// telling what's in it. Fewer come back at the end of the input, or when
+// its buffer holds fewer.
+fn peek_first_bytes(reader: &mut impl BufRead, n: usize) -> io::Result<&[u8]> {
+    let available = reader.fill_buf()?;
+    Ok(&available[..available.len().min(n)])
+}
+
+// The file at `path` decompressed, if it's in a format that's built in.
+// Only the file's own bytes are looked at, so what comes out of one format
+// is never taken for another.
+#[cfg_attr(
+    not(any(feature = "zstd", feature = "bzip2", feature = "xz2")),
+    allow(unused_mut, unused_variables)
+)]
+fn decompressed(path: &str, mut reader: Box<dyn BufRead>) -> io::Result<Box<dyn BufRead>> {
+    #[cfg(feature = "zstd")]
+    if is_zstd(path, &mut reader)? {
+        let decoder = zstd::Decoder::with_buffer(reader)?;
+        return Ok(Box::new(BufReader::new(decoder)));
+    }
+    // Concatenated bzip2 and xz streams are all decompressed, as `bzcat` and
+    // `xzcat` do
+    #[cfg(feature = "bzip2")]
+    if is_bzip2(path, &mut reader)? {
+        let decoder = bzip2::bufread::MultiBzDecoder::new(reader);
+        return Ok(Box::new(BufReader::new(decoder)));
+    }
+    #[cfg(feature = "xz2")]
+    if is_xz(path, &mut reader)? {
+        let decoder = xz2::bufread::XzDecoder::new_multi_decoder(reader);
+        return Ok(Box::new(BufReader::new(decoder)));
+    }
+    Ok(reader)
+}
+
+// Whether `get_buf_read()` would decompress the file at `path`
+#[cfg_attr(
+    not(any(feature = "zstd", feature = "bzip2", feature = "xz2")),
+    allow(unused_variables)
+)]
+fn is_compressed(path: &str, reader: &mut impl BufRead) -> io::Result<bool> {
+    #[cfg(feature = "zstd")]
+    if is_zstd(path, reader)? {
+        return Ok(true);
+    }
+    #[cfg(feature = "bzip2")]
+    if is_bzip2(path, reader)? {
+        return Ok(true);
+    }
+    #[cfg(feature = "xz2")]
+    if is_xz(path, reader)? {
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+// zstd files are recognised either by their extension or by the magic number
+// at their start
+#[cfg(feature = "zstd")]
+fn is_zstd(path: &str, reader: &mut impl BufRead) -> io::Result<bool> {
+    Ok(path.ends_with(".zst") || peek_first_bytes(reader, ZSTD_MAGIC.len())? == ZSTD_MAGIC)
+}
+
+// bzip2 files are recognised either by their extension or by the whole
+// stream header at their start, as text can start with "BZh" and a digit
+#[cfg(feature = "bzip2")]
+fn is_bzip2(path: &str, reader: &mut impl BufRead) -> io::Result<bool> {
+    let sniffed = match peek_first_bytes(reader, 10)? {
+        [b'B', b'Z', b'h', b'1'..=b'9', block @ ..] => {
+            BZIP2_BLOCK_MAGICS.iter().any(|m| m == block)
+        }
+        _ => false,
+    };
+    Ok(path.ends_with(".bz2") || sniffed)
+}
+
+// xz files are recognised either by their extension or by the magic number
+// at their start
+#[cfg(feature = "xz2")]
+fn is_xz(path: &str, reader: &mut impl BufRead) -> io::Result<bool> {
+    Ok(path.ends_with(".xz") || peek_first_bytes(reader, XZ_MAGIC.len())? == XZ_MAGIC)
+}
+
+type OpenedInput<R = Box<dyn BufRead>> = Result<Input<R>, Box<dyn Error>>;
+
+// An opened input along with the path it was opened from, if any
+struct Input<R> {
+    path: Option<String>,
+    reader: R,
+}
+
+impl<R> Input<R> {
+    fn new(path: impl Into<String>, reader: R) -> Self {
+        Input {
+            path: Some(path.into()),
+            reader,
         }
     }
+
+    fn stdin(reader: R) -> Self {
+        Input { path: None, reader }
+    }
 }
 
+// The default has no inputs and every option turned off. With no inputs
+// there's nothing to write: unlike the command line, standard input isn't
+// read in their place unless it's added.
+#[derive(Default)]
 pub struct Concatenator {
     inputs: Vec<InputSource>,
-    add_line_numbers: bool,
-    add_line_endings: bool,
+    options: CatOptions,
+    #[cfg(feature = "checksum")]
+    checksum: Option<ChecksumAlgo>,
+    #[cfg(feature = "flate2")]
+    gzip_output: bool,
+    create_dirs: bool,
+    output_buffer_capacity: Option<usize>,
+    number_state_file: Option<PathBuf>,
+    output_stderr: bool,
+    auto_number_width: bool,
+    hooks: InputHooks,
 }
 
 // When printing line numbers:
-// - indent 5 spaces before the number
-// - indent with tab after the number
-pub const PRE_LINE_NUM_INDENT: &str = "     ";
+// - right-justify the number in a field this wide
+// - indent with tab after the number, whose stops are this far apart when
+//   padding with spaces instead
+pub const DEFAULT_LINE_NUMBER_WIDTH: usize = 6;
 pub const POST_LINE_NUM_INDENT: &str = "\t";
+pub const DEFAULT_TABSTOP: usize = 8;
+
+pub const DEFAULT_ERROR_PREFIX: &str = "cat";
+
+// Longest line held back whole by default, for transforms like reversing
+pub const DEFAULT_MAX_BUFFERED_LINE: usize = 64 * 1024 * 1024;
+
+// How standard input is referred to in messages
+pub const STDIN_NAME: &str = "standard input";
 
 impl Concatenator {
     pub fn new(inputs: Vec<InputSource>) -> Concatenator {
         Concatenator {
             inputs,
-            add_line_numbers: false,
-            add_line_endings: false,
+            options: CatOptions::default(),
+            #[cfg(feature = "checksum")]
+            checksum: None,
+            #[cfg(feature = "flate2")]
+            gzip_output: false,
+            create_dirs: false,
+            output_buffer_capacity: None,
+            number_state_file: None,
+            output_stderr: false,
+            auto_number_width: false,
+            hooks: InputHooks::default(),
         }
     }
 
+    pub fn from_paths(paths: impl IntoIterator<Item = impl AsRef<Path>>) -> Concatenator {
+        paths
+            .into_iter()
+            .fold(Concatenator::default(), Concatenator::add_file)
+    }
+
+    // The files matching the glob `pattern`, in sorted order, for shells
+    // that don't expand patterns themselves. Matching nothing is an error.
+    #[cfg(feature = "glob")]
+    pub fn from_glob(pattern: &str) -> io::Result<Concatenator> {
+        let in_pattern = |e: glob::PatternError| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("{pattern}: {e}"))
+        };
+        let mut paths = glob::glob(pattern)
+            .map_err(in_pattern)?
+            .map(|path| path.map_err(io::Error::from))
+            .collect::<io::Result<Vec<_>>>()?;
+        if paths.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{pattern}: no files match"),
+            ));
+        }
+        paths.sort();
+        Ok(Concatenator::from_paths(paths))
+    }
+
+    pub fn add_file(mut self, path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref().to_string_lossy().into_owned();
+        self.inputs.push(InputSource::File(path));
+        self
+    }
+
+    // Add the regular files under the directory at `path`, as
+    // `InputSource::dir_files()` finds them
+    pub fn add_dir_recursive(mut self, path: impl AsRef<Path>) -> io::Result<Self> {
+        self.inputs.extend(InputSource::dir_files(path)?);
+        Ok(self)
+    }
+
+    pub fn add_stdin(mut self) -> Self {
+        self.inputs.push(InputSource::StdIn);
+        self
+    }
+
+    pub fn add_reader(mut self, reader: impl BufRead + 'static) -> Self {
+        self.inputs.push(InputSource::Reader(Box::new(reader)));
+        self
+    }
+
+    // Replace all the options at once, e.g. with ones shared with
+    // `concatenate_readers()`
+    pub fn with_options(mut self, options: CatOptions) -> Self {
+        self.options = options;
+        self
+    }
+
     pub fn with_line_numbers(mut self) -> Self {
-        self.add_line_numbers = true;
+        self.options.line_numbers = true;
         self
     }
 
-    pub fn with_line_endings(mut self) -> Self {
-        self.add_line_endings = true;
+    // Numbers wider than the field push the content to the right rather
+    // than being truncated
+    pub fn with_line_number_width(mut self, width: usize) -> Self {
+        self.options.line_number_width = width;
         self
     }
 
-    pub fn concatenate(self) -> io::Result<()> {
-        let ins = self
-            .inputs
-            .into_iter()
-            .map(InputSource::get_buf_read)
-            .collect();
+    // Size the number field to fit the last line's number, counting the
+    // lines of the files up front so it doesn't widen partway through. With
+    // any input that can't be read twice, like standard input, the width is
+    // left as it is.
+    pub fn with_auto_number_width(mut self) -> Self {
+        self.auto_number_width = true;
+        self
+    }
 
-        let mut out = BufWriter::new(io::stdout());
-        cat(ins, &mut out, self.add_line_numbers, self.add_line_endings)
+    pub fn with_number_style(mut self, style: NumberStyle) -> Self {
+        self.options.number_style = style;
+        self
     }
-}
 
-#[derive(PartialEq)]
-enum BufReadState {
-    StartOfLine,
-    MiddleOfLine,
-}
+    // Number only the lines matching `pattern`. The others are written
+    // without a number, and don't use one up.
+    pub fn with_number_matching(mut self, pattern: LinePattern) -> Self {
+        self.options.line_numbers = true;
+        self.options.number_matching = Some(pattern);
+        self
+    }
 
-fn cat<R, W>(
-    ins: Vec<Result<R, Box<dyn Error>>>,
-    out: &mut W,
-    line_nums: bool,
-    line_ends: bool,
-) -> io::Result<()>
-where
-    R: BufRead,
-    W: Write,
-{
-    use BufReadState::*;
+    // Write the number only on every `n`th line, counting them all. The
+    // others are written without a number, like unmatched ones.
+    pub fn with_line_number_every(mut self, n: usize) -> Self {
+        self.options.line_numbers = true;
+        self.options.line_number_every = n;
+        self
+    }
 
-    let mut line_count = 1;
-    let mut buf_read_state = StartOfLine;
+    // Keep line numbers to `max` at most, for parsers expecting a fixed
+    // width, wrapping back to 1 or leaving the gutter blank past it
+    pub fn with_line_number_cap(mut self, max: usize, mode: CapMode) -> Self {
+        self.options.line_number_cap = Some((max, mode));
+        self
+    }
 
-    'outer: for input in ins {
-        if let Err(e) = input {
-            writeln!(out, "cat: {e}")?;
-            out.flush()?;
-            buf_read_state = StartOfLine;
-            continue 'outer;
-        }
+    // When numbering, write `marker` in place of the number of blank lines.
+    // They still use a number up.
+    pub fn with_blank_line_marker(mut self, marker: String) -> Self {
+        self.options.blank_line_marker = Some(marker);
+        self
+    }
 
-        let mut input = input.unwrap();
-        'inner: loop {
-            let input_buffer = input.fill_buf()?;
+    // Hex numbers are lowercase. `prefix` marks them with `0x` or `0o`.
+    pub fn with_number_format(mut self, format: NumberFormat, prefix: bool) -> Self {
+        self.options.number_format = format;
+        self.options.number_prefix = prefix;
+        self
+    }
 
-            // Break inner loop if this input stream is exhausted
-            if input_buffer.is_empty() {
-                break 'inner;
-            }
+    // Pad line numbers with spaces by the display width of what comes before
+    // the content, rather than with a tab, so it lines up in a terminal
+    #[cfg(feature = "unicode-width")]
+    pub fn with_display_width_numbering(mut self) -> Self {
+        self.options.display_width_numbering = true;
+        self
+    }
 
-            // Add line numbers if configured, if we're at the start of a line
-            if buf_read_state == StartOfLine && line_nums {
-                write!(
-                    out,
-                    "{PRE_LINE_NUM_INDENT}{line_count}{POST_LINE_NUM_INDENT}"
-                )?;
-                line_count += 1;
-            }
+    // Pad line numbers with spaces up to the next tab stop, rather than with
+    // a tab, counting a character a column
+    pub fn with_replace_tabs_in_numbering(mut self) -> Self {
+        self.options.replace_tabs_in_numbering = true;
+        self
+    }
 
-            // Write the entire buffer or until newline, whichever comes first
-            let mut bytes_written =
-                out.write(input_buffer.splitn(2, |b| *b == b'\n').next().unwrap())?;
+    // Prefix each line with the offset of its first byte among all the
+    // input, in place of its number. Formatted like line numbers are.
+    pub fn with_byte_offsets(mut self) -> Self {
+        self.options.byte_offsets = true;
+        self
+    }
 
-            // If we didn't write the full buffer, we encountered a new line
-            // Otherwise, we either hit EOF, or are in the middle of a super long line
-            if bytes_written < input_buffer.len() {
-                buf_read_state = StartOfLine;
+    // Prefix each line with which input it started in, counting from 1, and
+    // the offset of its first byte within that input, as in `[file 2 @ 40] `
+    pub fn with_source_byte_gutter(mut self) -> Self {
+        self.options.source_byte_gutter = true;
+        self
+    }
 
-                // Write line endings if configured
-                if line_ends {
-                    write!(out, "$")?;
-                }
+    // Prefix each line with the time it's written at, in UTC, formatted by
+    // `format` like strftime would. `%Y`, `%m`, `%d`, `%H`, `%M`, `%S`, `%s`
+    // (seconds since the epoch) and `%%` are understood.
+    pub fn with_timestamp_prefix(mut self, format: String) -> Self {
+        self.options.timestamp_format = Some(format);
+        self
+    }
 
-                // Write newline character and advance counter
-                writeln!(out)?;
-                bytes_written += 1;
-            } else {
-                buf_read_state = MiddleOfLine;
-            }
+    // Where the time for `with_timestamp_prefix()` comes from, in place of
+    // the system clock
+    pub fn with_clock(mut self, clock: impl Fn() -> SystemTime + Send + Sync + 'static) -> Self {
+        self.options.clock = Arc::new(clock);
+        self
+    }
 
-            input.consume(bytes_written);
-            out.flush()?;
-        }
+    pub fn with_per_file_numbering(mut self) -> Self {
+        self.options.per_file_numbering = true;
+        self
     }
-    Ok(())
-}
 
-#[cfg(test)]
-mod cat_tests {
-    use std::{
-        io::{self, Cursor},
-        str,
-    };
+    // Write `separator` on a line of its own between inputs with content.
+    // Inputs that failed or turned out empty aren't separated.
+    pub fn with_file_separator(mut self, separator: String) -> Self {
+        self.options.file_separator = Some(separator);
+        self
+    }
 
-    use crate::{POST_LINE_NUM_INDENT, PRE_LINE_NUM_INDENT};
+    // Start each input on a line of its own, ending the last line of the
+    // one before if it didn't, rather than carry that line on
+    pub fn with_force_line_break_between_files(mut self) -> Self {
+        self.options.force_line_break_between_files = true;
+        self
+    }
 
-    use super::cat;
+    // Carry on a line an error cut into after the error, without a number
+    // of its own, rather than start a new numbered line
+    pub fn with_number_blank_as_continuation(mut self) -> Self {
+        self.options.number_blank_as_continuation = true;
+        self
+    }
 
-    const INPUT_STREAM_1: &str = "This is the first file...
-Second line of first file now
-Not ending with a new line";
-    const INPUT_STREAM_2: &str = "This is the second file...
-Second line of second file now
-Going to end with a new line
-";
-    const INPUT_STREAM_3: &str = "This is the third file...
-Second line of third file now
-Not ending with a new line";
+    pub fn with_line_endings(mut self) -> Self {
+        self.options.line_ends = true;
+        self
+    }
 
-    const ERROR_1: &str = "Oops, something went wrong!";
+    // Show carriage returns as `^M`, so that what they'd overwrite in a
+    // terminal can be seen. With line ends shown, one ending a line is shown
+    // as `^M$` either way.
+    pub fn with_show_carriage_returns(mut self) -> Self {
+        self.options.show_carriage_returns = true;
+        self
+    }
 
-    #[test]
-    fn no_ins_no_out() -> io::Result<()> {
-        let ins = vec![Ok(Cursor::new(String::new()))];
-        let mut out = Vec::<u8>::default();
-        cat(ins, &mut out, false, false)?;
+    // Drop ANSI escape sequences, like the ones coloring logs, from the
+    // content, leaving everything else as it is
+    pub fn with_strip_ansi(mut self) -> Self {
+        self.options.strip_ansi = true;
+        self
+    }
 
-        assert_eq!(out.len(), 0);
-        Ok(())
+    // Fold letters in the content to lower case, ASCII ones only unless
+    // Unicode case is asked for
+    pub fn with_lowercase(mut self) -> Self {
+        self.options.case_fold = Some(CaseFold::Lower);
+        self
     }
 
-    #[test]
-    fn one_in_correct_out() -> io::Result<()> {
-        let ins = vec![Ok(Cursor::new(INPUT_STREAM_1))];
-        let mut out = Vec::<u8>::default();
-        cat(ins, &mut out, false, false)?;
+    // Fold letters in the content to upper case, ASCII ones only unless
+    // Unicode case is asked for
+    pub fn with_uppercase(mut self) -> Self {
+        self.options.case_fold = Some(CaseFold::Upper);
+        self
+    }
 
-        assert_eq!(str::from_utf8(&out).unwrap(), INPUT_STREAM_1);
-        Ok(())
+    // Fold case over all of Unicode rather than just ASCII, leaving bytes
+    // that aren't valid UTF-8 as they are
+    pub fn with_unicode_case(mut self) -> Self {
+        self.options.unicode_case = true;
+        self
     }
 
-    #[test]
-    fn one_in_error_correct_out() -> io::Result<()> {
-        let ins: Vec<Result<Cursor<Vec<u8>>, _>> = vec![Err(ERROR_1.into())];
-        let mut out = Vec::<u8>::default();
-        cat(ins, &mut out, false, false)?;
+    // Mark the end of each line with `␊`, or `␍␊` where it ends with CRLF,
+    // instead of `$`
+    pub fn with_visible_line_endings(mut self) -> Self {
+        self.options.visible_line_endings = true;
+        self
+    }
 
-        assert_eq!(str::from_utf8(&out).unwrap(), format!("cat: {ERROR_1}\n"));
-        Ok(())
+    pub fn with_reverse_lines(mut self) -> Self {
+        self.options.reverse_lines = true;
+        self
     }
 
-    #[test]
-    fn multiple_ins_correct_out() -> io::Result<()> {
-        let ins = vec![
-            Ok(Cursor::new(INPUT_STREAM_1)),
-            Ok(Cursor::new(INPUT_STREAM_2)),
-            Ok(Cursor::new(INPUT_STREAM_3)),
-        ];
-        let mut out = Vec::<u8>::default();
-        cat(ins, &mut out, false, false)?;
+    // Strip spaces and tabs from the end of each line
+    pub fn with_trim_trailing_whitespace(mut self) -> Self {
+        self.options.trim_trailing_whitespace = true;
+        self
+    }
 
-        assert_eq!(
-            str::from_utf8(&out).unwrap(),
-            format!("{INPUT_STREAM_1}{INPUT_STREAM_2}{INPUT_STREAM_3}")
-        );
-        Ok(())
+    // Limit the content of each line to `cols` characters. Wrapped rows
+    // aren't numbered or marked with `$` separately from their line.
+    pub fn with_max_line_width(mut self, cols: usize, mode: WrapMode) -> Self {
+        self.options.max_line_width = Some((cols, mode));
+        self
     }
 
-    #[test]
-    fn multiple_ins_with_error_correct_out() -> io::Result<()> {
-        let ins = vec![
-            Ok(Cursor::new(INPUT_STREAM_1)),
-            Err(ERROR_1.into()),
-            Ok(Cursor::new(INPUT_STREAM_2)),
-            Ok(Cursor::new(INPUT_STREAM_3)),
-        ];
-        let mut out = Vec::<u8>::default();
-        cat(ins, &mut out, false, false)?;
+    // Write lines longer than `bytes` as they're read, untransformed, rather
+    // than hold them whole for trimming, wrapping, reversing or highlighting,
+    // or for matching, dropping or rewriting them. A warning is given the
+    // first time.
+    pub fn with_max_buffered_line(mut self, bytes: usize) -> Self {
+        self.options.max_buffered_line = bytes;
+        self
+    }
 
-        assert_eq!(
-            str::from_utf8(&out).unwrap(),
-            format!("{INPUT_STREAM_1}cat: {ERROR_1}\n{INPUT_STREAM_2}{INPUT_STREAM_3}")
-        );
-        Ok(())
+    // Replace tabs with spaces up to the next multiple of `tabstop` columns,
+    // like `expand`. Columns count everything written on the line, including
+    // the filename and line number, whose trailing tab is expanded too.
+    pub fn with_expand_tabs(mut self, tabstop: usize) -> Self {
+        self.options.tabstop = Some(tabstop.max(1));
+        self
     }
 
-    #[test]
-    fn line_nums_correct_out() -> io::Result<()> {
-        let ins = vec![
-            Ok(Cursor::new(INPUT_STREAM_1)),
-            Ok(Cursor::new(INPUT_STREAM_2)),
-            Ok(Cursor::new(INPUT_STREAM_3)),
-        ];
-        let mut out = Vec::<u8>::default();
-        cat(ins, &mut out, true, false)?;
+    // Prefix each line with the path of the input it starts in, like
+    // `grep -H`. When numbering too, the path comes before the number.
+    pub fn with_filename_prefix(mut self) -> Self {
+        self.options.filename_prefix = true;
+        self
+    }
 
-        let (lines_1, lines_2, lines_3) = (
-            INPUT_STREAM_1.lines().collect::<Vec<_>>(),
-            INPUT_STREAM_2.lines().collect::<Vec<_>>(),
-            INPUT_STREAM_3.lines().collect::<Vec<_>>(),
-        );
+    // Text written around the content of every line, after its number and
+    // before its `$`
+    pub fn with_line_prefix(mut self, prefix: String) -> Self {
+        self.options.line_prefix = Some(prefix);
+        self
+    }
 
-        let expected_out = vec![
-            format!(
-                "{PRE_LINE_NUM_INDENT}1{POST_LINE_NUM_INDENT}{}\n",
-                lines_1[0]
-            ),
-            format!(
-                "{PRE_LINE_NUM_INDENT}2{POST_LINE_NUM_INDENT}{}\n",
+    pub fn with_line_suffix(mut self, suffix: String) -> Self {
+        self.options.line_suffix = Some(suffix);
+        self
+    }
+
+    // Text written once before all the content, without a line number
+    pub fn with_prefix(mut self, prefix: String) -> Self {
+        self.options.prefix = Some(prefix);
+        self
+    }
+
+    // Text written once after all the content, right after its last byte
+    pub fn with_suffix(mut self, suffix: String) -> Self {
+        self.options.suffix = Some(suffix);
+        self
+    }
+
+    // Terminate every line written with `ending`, whatever ended it in the
+    // input. Carriage returns in the input are left alone.
+    pub fn with_output_line_ending(mut self, ending: LineEnding) -> Self {
+        self.options.line_ending = ending;
+        self
+    }
+
+    // End every line the way most lines in the first chunk of the first
+    // input end, CRLF or LF. Carriage returns ending lines are dropped so
+    // they don't double up.
+    pub fn with_preserve_dominant_ending(mut self) -> Self {
+        self.options.preserve_dominant_ending = true;
+        self
+    }
+
+    pub fn with_input_encoding(mut self, encoding: Encoding) -> Self {
+        self.options.input_encoding = encoding;
+        self
+    }
+
+    // Replace each input byte that's a key of `replacements` with its value,
+    // e.g. to strip `\r` or turn `\t` into spaces. This happens as the input
+    // is read, before it's decoded. Newlines are never replaced.
+    pub fn with_replace_bytes(mut self, replacements: impl Into<HashMap<u8, Vec<u8>>>) -> Self {
+        self.options.replace_bytes = replacements.into();
+        self
+    }
+
+    // Skip inputs that look binary, with a warning on stderr
+    pub fn with_skip_binary(mut self) -> Self {
+        self.options.skip_binary = true;
+        self
+    }
+
+    // List the inputs, and whether each can be opened, without reading them
+    pub fn with_dry_run(mut self) -> Self {
+        self.options.dry_run = true;
+        self
+    }
+
+    // Count the lines matching `pattern` in each input, writing `path: N`
+    // for each of them instead of their content
+    pub fn with_grep_count(mut self, pattern: LinePattern) -> Self {
+        self.options.grep_count = Some(pattern);
+        self
+    }
+
+    // Skip FIFOs that have nothing to read yet, rather than waiting for
+    // something to write to them
+    pub fn with_no_block(mut self) -> Self {
+        self.options.no_block = true;
+        self
+    }
+
+    // Skip files that don't exist without a word, rather than report them.
+    // Other errors opening files are still reported.
+    pub fn with_ignore_missing(mut self) -> Self {
+        self.options.ignore_missing = true;
+        self
+    }
+
+    // Skip files bigger than this many bytes, with a warning. Standard
+    // input and readers aren't checked
+    pub fn with_max_file_size(mut self, bytes: u64) -> Self {
+        self.options.max_file_size = Some(bytes);
+        self
+    }
+
+    // Write only the last `n` lines of the output, like `tail`, holding on
+    // to no more than those while reading. Lines joined by
+    // `with_replace_newlines()` can't be told apart, so it can't be combined
+    // with that.
+    pub fn with_tail_lines(mut self, n: usize) -> Self {
+        self.options.tail_lines = Some(n);
+        self
+    }
+
+    // Stop the whole run once this many bytes have been written, with a
+    // warning, and mark the stats as cut short
+    pub fn with_max_total_bytes(mut self, bytes: u64) -> Self {
+        self.options.max_total_bytes = Some(bytes);
+        self
+    }
+
+    // Fail with a timeout error when standard input or a named pipe has had
+    // nothing to read for this long. Only honored on Unix, where there's a
+    // way to wait on them that can give up; elsewhere they block as usual.
+    pub fn with_input_timeout(mut self, timeout: Duration) -> Self {
+        self.options.input_timeout = Some(timeout);
+        self
+    }
+
+    // Note on stderr each input as it starts being read. Those that can't
+    // be opened get their error instead.
+    pub fn with_verbose(mut self) -> Self {
+        self.options.verbose = true;
+        self
+    }
+
+    // Write nothing but content: no warnings, and no errors among the
+    // output. Errors are still returned in the stats.
+    pub fn with_quiet(mut self) -> Self {
+        self.options.quiet = true;
+        self
+    }
+
+    // Call `hook` as each input starts being read. Inputs that can't be
+    // opened, or are skipped, aren't read.
+    pub fn on_input_start(mut self, hook: impl FnMut(&InputDesc) + 'static) -> Self {
+        self.hooks.start = Some(Box::new(hook));
+        self
+    }
+
+    // Call `hook` with what was read from each input once it's been read
+    pub fn on_input_end(mut self, hook: impl FnMut(&InputDesc, InputStats) + 'static) -> Self {
+        self.hooks.end = Some(Box::new(hook));
+        self
+    }
+
+    // Rewrite the content of each line with `transform` before anything
+    // else, like numbering, is done with it. The line keeps its ending, even
+    // if `transform` leaves nothing of its content.
+    pub fn with_line_transform(
+        mut self,
+        transform: impl for<'a> FnMut(&'a [u8]) -> Cow<'a, [u8]> + 'static,
+    ) -> Self {
+        self.hooks.transform = Some(Box::new(transform));
+        self
+    }
+
+    // Drop the lines whose content `filter` returns false for, after any
+    // transform. Dropped lines aren't numbered, and the numbers go on
+    // without a gap unless asked to count them too.
+    pub fn with_line_filter(mut self, filter: impl FnMut(&[u8]) -> bool + 'static) -> Self {
+        self.hooks.filter = Some(Box::new(filter));
+        self
+    }
+
+    // Count lines dropped by the line filter in the numbering, leaving a
+    // gap where each was
+    pub fn with_number_filtered_lines(mut self) -> Self {
+        self.options.number_filtered_lines = true;
+        self
+    }
+
+    // Summarize what was read on stderr once done
+    pub fn with_summary(mut self) -> Self {
+        self.options.summary = true;
+        self
+    }
+
+    // Write the lines of exactly two inputs in turn, like `paste -d '\n'`
+    pub fn with_interleave(mut self) -> Self {
+        self.options.interleave = true;
+        self
+    }
+
+    // Drop blank lines from the start and/or end of the output, leaving
+    // those in between alone
+    pub fn with_trim_blank_edges(mut self, leading: bool, trailing: bool) -> Self {
+        self.options.trim_leading_blanks = leading;
+        self.options.trim_trailing_blanks = trailing;
+        self
+    }
+
+    // Drop each line that's the same as the one written before it, like
+    // `uniq`, including across the end of one input and the start of the
+    // next. A last line without a newline is always written.
+    pub fn with_unique_adjacent(mut self) -> Self {
+        self.options.unique_adjacent = true;
+        self
+    }
+
+    // Split the input into records ending with `separator` instead of lines
+    // ending with a newline, e.g. NUL for `find -print0` output. Numbering
+    // and shown ends go by record, and each record is still ended with
+    // `separator` in the output.
+    pub fn with_record_separator(mut self, separator: u8) -> Self {
+        self.options.record_separator = separator;
+        self
+    }
+
+    // Join lines with `separator` in place of the newline ending each, like
+    // `tr '\n' ','`. Shown line ends come before it, and with a trailing
+    // newline ensured, the output still ends with one.
+    pub fn with_replace_newlines(mut self, separator: Vec<u8>) -> Self {
+        self.options.newline_replacement = Some(separator);
+        self
+    }
+
+    // End the output with a newline if it doesn't already, unless it's empty
+    pub fn with_ensure_trailing_newline(mut self) -> Self {
+        self.options.ensure_trailing_newline = true;
+        self
+    }
+
+    // Stop at the first input that can't be read, returning its error from
+    // `concatenate()` instead of reporting it and moving on
+    pub fn with_strict(mut self) -> Self {
+        self.options.strict = true;
+        self
+    }
+
+    // Warn on stderr about each input that ends some lines with LF and
+    // others with CRLF
+    pub fn with_warn_mixed_endings(mut self) -> Self {
+        self.options.warn_mixed_endings = true;
+        self
+    }
+
+    // Output binary inputs even when skipping them was asked for
+    pub fn with_force(mut self) -> Self {
+        self.options.force = true;
+        self
+    }
+
+    // Color the lines of files whose extension names a known language. It
+    // only applies when writing to a terminal, and never to standard input.
+    #[cfg(feature = "syntect")]
+    pub fn with_syntax_highlight(mut self) -> Self {
+        self.options.syntax_highlight = true;
+        self
+    }
+
+    // Name errors are reported under, as in `{prefix}: {error}`
+    pub fn with_error_prefix(mut self, prefix: String) -> Self {
+        self.options.error_prefix = prefix;
+        self
+    }
+
+    // Write each line as a JSON object on its own line, holding the path of
+    // the input it started in (null for standard input), its number and its
+    // text. Numbering follows the per-file policy.
+    #[cfg(feature = "serde_json")]
+    pub fn with_ndjson(mut self) -> Self {
+        self.options.format = OutputFormat::Ndjson;
+        self
+    }
+
+    // Write the lines as a single JSON array of the objects `with_ndjson()`
+    // writes one per line
+    #[cfg(feature = "serde_json")]
+    pub fn with_json(mut self) -> Self {
+        self.options.format = OutputFormat::Json;
+        self
+    }
+
+    // Encode the combined output as base64, wrapping encoded lines every
+    // `wrap` characters (76 when None, never when Some(0))
+    #[cfg(feature = "base64")]
+    pub fn with_base64_encode(mut self, wrap: Option<usize>) -> Self {
+        self.options.transform = OutputTransform::Base64Encode {
+            wrap: wrap.unwrap_or(BASE64_DEFAULT_WRAP),
+        };
+        self
+    }
+
+    #[cfg(feature = "base64")]
+    pub fn with_base64_decode(mut self) -> Self {
+        self.options.transform = OutputTransform::Base64Decode;
+        self
+    }
+
+    // Escape the content as the body of a C string literal. Newlines
+    // become `\n` too, unless lines are folded or truncated, in which case
+    // each is still followed by a real one.
+    pub fn with_c_escape(mut self) -> Self {
+        self.options.transform = OutputTransform::CEscape;
+        self
+    }
+
+    // Take a digest of everything written, returned in the stats once all
+    // the content has been written
+    #[cfg(feature = "checksum")]
+    pub fn with_checksum(mut self, algo: ChecksumAlgo) -> Self {
+        self.checksum = Some(algo);
+        self
+    }
+
+    // Compress everything written with gzip
+    #[cfg(feature = "flate2")]
+    pub fn with_gzip_output(mut self) -> Self {
+        self.gzip_output = true;
+        self
+    }
+
+    // Checks that each file input can be opened for reading and isn't a
+    // directory, without reading any of it. Other inputs, like standard
+    // input, are taken to be fine, so that nothing is consumed.
+    pub fn validate(&self) -> Vec<Result<(), CatError>> {
+        let check = |path: &str| -> Result<(), String> {
+            let file = fs::File::open(path).map_err(|e| format!("{path}: {e}"))?;
+            let metadata = file.metadata().map_err(|e| format!("{path}: {e}"))?;
+            if metadata.is_dir() {
+                return Err(format!("{path}: Is a directory"));
+            }
+            Ok(())
+        };
+        self.inputs
+            .iter()
+            .enumerate()
+            .map(|(index, input)| match input {
+                InputSource::File(path) => check(path).map_err(|e| CatError::new(index, e)),
+                _ => Ok(()),
+            })
+            .collect()
+    }
+
+    // Errors reading the inputs are reported among the output, and returned
+    // in the stats once everything else has been written
+    pub fn concatenate(mut self) -> io::Result<CatStats> {
+        let to_terminal = match self.output_stderr {
+            true => io::stderr().is_terminal(),
+            false => io::stdout().is_terminal(),
+        };
+        if !to_terminal {
+            self.options.syntax_highlight = false;
+        }
+        // Standard output closing early, like by `head`, comes back as the
+        // stats gathered until then
+        self.concatenate_to_either(io::stdout(), io::stderr())
+    }
+
+    // Have `concatenate()` write to standard error instead of standard
+    // output, for when that's kept for something else. Warnings still go
+    // there too, set apart from content by their `cat:` prefix.
+    pub fn with_output_stderr(mut self) -> Self {
+        self.output_stderr = true;
+        self
+    }
+
+    // Create the missing parent directories of the file given to
+    // `write_to_path()`
+    pub fn with_create_dirs(mut self) -> Self {
+        self.create_dirs = true;
+        self
+    }
+
+    // Like `concatenate()`, but writes to the file at `path`, replacing its
+    // content. Failing to open it is an error of its own rather than output.
+    pub fn write_to_path(mut self, path: impl AsRef<Path>) -> io::Result<CatStats> {
+        let path = path.as_ref();
+        let in_path = |e: io::Error| io::Error::new(e.kind(), format!("{}: {e}", path.display()));
+        if self.create_dirs {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(in_path)?;
+            }
+        }
+        let file = fs::File::create(path).map_err(in_path)?;
+
+        self.options.syntax_highlight = false;
+        let out = self.buffered(file);
+        self.concatenate_to(out)
+    }
+
+    // Like `concatenate()`, but writes to a writer the caller holds on to,
+    // such as a locked stdout. It isn't buffered any further.
+    pub fn concatenate_into<W: Write>(mut self, out: &mut W) -> io::Result<CatStats> {
+        self.options.syntax_highlight = false;
+        self.concatenate_to(out)
+    }
+
+    // Carry line numbering on from the last run, starting from the number
+    // saved in the file at `path` and saving the next one there once done.
+    // Numbering starts from 1 if the file doesn't exist yet. Runs sharing
+    // the file at the same time aren't guarded against.
+    pub fn with_number_state_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.number_state_file = Some(path.into());
+        self
+    }
+
+    // Size of the buffer output is gathered in before it's written out,
+    // which is 8 KiB by default
+    pub fn with_output_buffer_capacity(mut self, capacity: usize) -> Self {
+        self.output_buffer_capacity = Some(capacity);
+        self
+    }
+
+    fn buffered<W: Write>(&self, out: W) -> BufWriter<W> {
+        match self.output_buffer_capacity {
+            Some(capacity) => BufWriter::with_capacity(capacity, out),
+            None => BufWriter::new(out),
+        }
+    }
+
+    // Writes to `stdout`, or to `stderr` when `with_output_stderr()` asked
+    fn concatenate_to_either<O: Write, E: Write>(
+        self,
+        stdout: O,
+        stderr: E,
+    ) -> io::Result<CatStats> {
+        match self.output_stderr {
+            true => {
+                let out = self.buffered(stderr);
+                self.concatenate_to(out)
+            }
+            false => {
+                let out = self.buffered(stdout);
+                self.concatenate_to(out)
+            }
+        }
+    }
+
+    // Writes to `out` through whichever of the checksum and compression are
+    // asked for. The checksum covers the bytes that reach `out`.
+    fn concatenate_to<W: Write>(self, out: W) -> io::Result<CatStats> {
+        #[cfg(feature = "checksum")]
+        if let Some(algo) = self.checksum {
+            let mut out = ChecksumWriter::new(out, algo);
+            let mut stats = self.compress_into(&mut out)?;
+            stats.checksum = Some(out.hex_digest());
+            return Ok(stats);
+        }
+
+        let mut out = out;
+        self.compress_into(&mut out)
+    }
+
+    fn compress_into<W: Write>(self, out: &mut W) -> io::Result<CatStats> {
+        #[cfg(feature = "flate2")]
+        if self.gzip_output {
+            let mut out = GzipWriter::new(out);
+            let stats = self.cat_inputs(&mut out)?;
+            ignore_broken_pipe(out.finish().and_then(|out| out.flush()))?;
+            return Ok(stats);
+        }
+
+        self.cat_inputs(out)
+    }
+
+    fn cat_inputs<W: Write>(mut self, out: &mut W) -> io::Result<CatStats> {
+        let mut err = self.warnings();
+        let ins = self.open_inputs(&mut err)?;
+
+        if self.options.dry_run {
+            return list_inputs(ins, out, &self.options);
+        }
+        if let Some(pattern) = &self.options.grep_count {
+            return count_matches(ins, out, pattern, &self.options);
+        }
+        let stats = cat_hooked(ins, out, &mut err, &self.options, self.hooks)?;
+        if let Some(path) = &self.number_state_file {
+            write_number_state(path, &stats)?;
+        }
+        Ok(stats)
+    }
+
+    fn warnings(&self) -> Box<dyn Write> {
+        match self.options.quiet {
+            true => Box::new(io::sink()),
+            false => Box::new(io::stderr()),
+        }
+    }
+
+    // Opens the inputs, settling the options that depend on them first
+    fn open_inputs<E: Write>(&mut self, err: &mut E) -> io::Result<Vec<OpenedInput>> {
+        if let Some(path) = &self.number_state_file {
+            self.options.first_line_number = read_number_state(path)?;
+        }
+        if self.auto_number_width {
+            if let Some(lines) = count_lines(&self.inputs, &self.options) {
+                let last = (self.options.first_line_number + lines).saturating_sub(1);
+                self.options.line_number_width = last.max(1).to_string().len();
+            }
+        }
+        let inputs = drop_reused_stdin(std::mem::take(&mut self.inputs), err, &self.options)?;
+        let mut ins = Vec::with_capacity(inputs.len());
+        for input in inputs {
+            ins.extend(open_input(input, &self.options, err)?);
+        }
+        if self.options.interleave {
+            ins = interleave(ins)?;
+        }
+        Ok(ins)
+    }
+
+    // Where a reader keeps what's been catted until it's read, compressed
+    // and checksummed as asked
+    fn read_ahead(&self) -> ReadAhead {
+        ReadAhead {
+            buf: Vec::new(),
+            #[cfg(feature = "flate2")]
+            gzip: self.gzip_output.then(|| GzipWriter::new(Vec::new())),
+            #[cfg(feature = "checksum")]
+            checksum: self
+                .checksum
+                .map(|algo| ChecksumWriter::new(io::sink(), algo)),
+        }
+    }
+}
+
+// How many lines the inputs have between them, if they're all regular files
+// or bytes that are catted as they are. Pipes, compressed files, decoded or
+// replaced bytes and files that can't be read all leave it unknown.
+fn count_lines(inputs: &[InputSource], options: &CatOptions) -> Option<usize> {
+    if options.input_encoding != Encoding::Utf8 || !options.replace_bytes.is_empty() {
+        return None;
+    }
+    let separator = options.record_separator;
+    let mut lines = 0;
+    for input in inputs {
+        let mut reader: Box<dyn BufRead + '_> = match input {
+            // Checked before opening, as opening a FIFO waits for a writer
+            InputSource::File(path) if fs::metadata(path).ok()?.is_file() => {
+                let mut reader = BufReader::new(fs::File::open(path).ok()?);
+                if is_compressed(path, &mut reader).ok()? {
+                    return None;
+                }
+                Box::new(reader)
+            }
+            InputSource::Bytes(bytes) => Box::new(bytes.as_slice()),
+            _ => return None,
+        };
+        let mut last = None;
+        loop {
+            let chunk = reader.fill_buf().ok()?;
+            let [.., end] = chunk else { break };
+            lines += chunk.iter().filter(|&&b| b == separator).count();
+            last = Some(*end);
+            let len = chunk.len();
+            reader.consume(len);
+        }
+        // A last line without a separator is still a line
+        if last.is_some_and(|b| b != separator) {
+            lines += 1;
+        }
+    }
+    Some(lines)
+}
+
+// Saves the number the next run is to carry on from in the state file at
+// `path`
+fn write_number_state(path: &Path, stats: &CatStats) -> io::Result<()> {
+    fs::write(path, format!("{}\n", stats.next_line_number))
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))
+}
+
+// The line number saved by an earlier run in the state file at `path`, or
+// 1 without one
+fn read_number_state(path: &Path) -> io::Result<usize> {
+    let in_path = |e: &dyn Display| format!("{}: {e}", path.display());
+    match fs::read_to_string(path) {
+        Ok(state) => match state.trim().parse() {
+            Ok(0) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                in_path(&"line numbers start at 1"),
+            )),
+            parsed => parsed.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, in_path(&e))),
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(1),
+        Err(e) => Err(io::Error::new(e.kind(), in_path(&e))),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    // One JSON object per line, reporting where it came from
+    #[cfg(feature = "serde_json")]
+    Ndjson,
+    // The same objects, as a single JSON array
+    #[cfg(feature = "serde_json")]
+    Json,
+}
+
+// Tells the time lines are timestamped with
+pub type Clock = Arc<dyn Fn() -> SystemTime + Send + Sync>;
+
+// Everything that controls how `cat()` renders its inputs. Start from the
+// default and set what's needed, e.g.
+// `CatOptions { line_numbers: true, ..Default::default() }`.
+#[derive(Clone)]
+pub struct CatOptions {
+    pub line_numbers: bool,
+    pub line_number_width: usize,
+    pub number_format: NumberFormat,
+    pub number_prefix: bool,
+    pub number_style: NumberStyle,
+    pub number_matching: Option<LinePattern>,
+    // The number the first line is given
+    pub first_line_number: usize,
+    // Numbers are written on every this many lines; 0 counts as 1
+    pub line_number_every: usize,
+    // Whether lines dropped by a line filter still take up a number
+    pub number_filtered_lines: bool,
+    pub blank_line_marker: Option<String>,
+    pub line_number_cap: Option<(usize, CapMode)>,
+    pub byte_offsets: bool,
+    pub source_byte_gutter: bool,
+    pub timestamp_format: Option<String>,
+    pub clock: Clock,
+    pub per_file_numbering: bool,
+    pub file_separator: Option<String>,
+    pub force_line_break_between_files: bool,
+    // By default, what follows an error that cut into a line starts a new
+    // line, numbered and prefixed as such. This has it carry on the line
+    // instead.
+    pub number_blank_as_continuation: bool,
+    pub line_ends: bool,
+    // Shows line ends as control pictures instead, in place of `$`
+    pub visible_line_endings: bool,
+    pub show_carriage_returns: bool,
+    pub strip_ansi: bool,
+    pub case_fold: Option<CaseFold>,
+    pub unicode_case: bool,
+    pub reverse_lines: bool,
+    pub trim_trailing_whitespace: bool,
+    pub max_line_width: Option<(usize, WrapMode)>,
+    // Longest line held back for the transforms that need all of it
+    pub max_buffered_line: usize,
+    pub tabstop: Option<usize>,
+    pub filename_prefix: bool,
+    pub display_width_numbering: bool,
+    pub replace_tabs_in_numbering: bool,
+    pub syntax_highlight: bool,
+    pub error_prefix: String,
+    pub prefix: Option<String>,
+    pub suffix: Option<String>,
+    pub line_prefix: Option<String>,
+    pub line_suffix: Option<String>,
+    pub line_ending: LineEnding,
+    // What ends each line of the input, in place of a newline
+    pub record_separator: u8,
+    // Written in place of `line_ending` at the end of each line
+    pub newline_replacement: Option<Vec<u8>>,
+    // Overrides `line_ending` with whichever ending the first input mostly uses
+    pub preserve_dominant_ending: bool,
+    pub input_encoding: Encoding,
+    pub replace_bytes: HashMap<u8, Vec<u8>>,
+    pub skip_binary: bool,
+    pub force: bool,
+    pub warn_mixed_endings: bool,
+    pub strict: bool,
+    pub ensure_trailing_newline: bool,
+    pub trim_leading_blanks: bool,
+    pub unique_adjacent: bool,
+    // Only honored by `Concatenator`, like `dry_run`
+    pub interleave: bool,
+    pub summary: bool,
+    pub verbose: bool,
+    pub quiet: bool,
+    pub no_block: bool,
+    pub max_file_size: Option<u64>,
+    pub max_total_bytes: Option<u64>,
+    pub tail_lines: Option<usize>,
+    pub ignore_missing: bool,
+    pub input_timeout: Option<Duration>,
+    pub trim_trailing_blanks: bool,
+    // Only honored by `Concatenator`, which opens the inputs itself
+    pub dry_run: bool,
+    // Only honored by `Concatenator`, like `dry_run`
+    pub grep_count: Option<LinePattern>,
+    pub format: OutputFormat,
+    pub transform: OutputTransform,
+}
+
+impl Default for CatOptions {
+    fn default() -> Self {
+        CatOptions {
+            line_numbers: false,
+            line_number_width: DEFAULT_LINE_NUMBER_WIDTH,
+            number_format: NumberFormat::Dec,
+            number_prefix: false,
+            number_style: NumberStyle::GnuRight,
+            number_matching: None,
+            first_line_number: 1,
+            line_number_every: 1,
+            number_filtered_lines: false,
+            blank_line_marker: None,
+            line_number_cap: None,
+            byte_offsets: false,
+            source_byte_gutter: false,
+            timestamp_format: None,
+            clock: Arc::new(SystemTime::now),
+            per_file_numbering: false,
+            file_separator: None,
+            force_line_break_between_files: false,
+            number_blank_as_continuation: false,
+            line_ends: false,
+            visible_line_endings: false,
+            show_carriage_returns: false,
+            strip_ansi: false,
+            case_fold: None,
+            unicode_case: false,
+            reverse_lines: false,
+            trim_trailing_whitespace: false,
+            max_line_width: None,
+            max_buffered_line: DEFAULT_MAX_BUFFERED_LINE,
+            tabstop: None,
+            filename_prefix: false,
+            display_width_numbering: false,
+            replace_tabs_in_numbering: false,
+            syntax_highlight: false,
+            error_prefix: DEFAULT_ERROR_PREFIX.to_string(),
+            prefix: None,
+            suffix: None,
+            line_prefix: None,
+            line_suffix: None,
+            line_ending: LineEnding::Lf,
+            record_separator: b'\n',
+            newline_replacement: None,
+            preserve_dominant_ending: false,
+            input_encoding: Encoding::Utf8,
+            replace_bytes: HashMap::new(),
+            skip_binary: false,
+            force: false,
+            warn_mixed_endings: false,
+            strict: false,
+            ensure_trailing_newline: false,
+            trim_leading_blanks: false,
+            unique_adjacent: false,
+            interleave: false,
+            summary: false,
+            verbose: false,
+            quiet: false,
+            no_block: false,
+            max_file_size: None,
+            max_total_bytes: None,
+            tail_lines: None,
+            ignore_missing: false,
+            input_timeout: None,
+            trim_trailing_blanks: false,
+            dry_run: false,
+            grep_count: None,
+            format: OutputFormat::Text,
+            transform: OutputTransform::Identity,
+        }
+    }
+}
+
+#[cfg(feature = "checksum")]
+#[derive(Clone, Copy)]
+pub enum ChecksumAlgo {
+    Sha256,
+    Crc32,
+}
+
+#[cfg(feature = "checksum")]
+enum Hasher {
+    Sha256(sha2::Sha256),
+    Crc32(crc32fast::Hasher),
+}
+
+// Compresses everything written with gzip. `cat()` flushes after every line,
+// which would bloat the compressed stream, so flushes wait for `finish()`.
+#[cfg(feature = "flate2")]
+struct GzipWriter<W: Write>(flate2::write::GzEncoder<W>);
+
+#[cfg(feature = "flate2")]
+impl<W: Write> GzipWriter<W> {
+    fn new(inner: W) -> Self {
+        GzipWriter(flate2::write::GzEncoder::new(
+            inner,
+            flate2::Compression::default(),
+        ))
+    }
+
+    fn finish(self) -> io::Result<W> {
+        self.0.finish()
+    }
+}
+
+#[cfg(feature = "flate2")]
+impl<W: Write> Write for GzipWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Passes everything through to the inner writer while hashing it
+#[cfg(feature = "checksum")]
+struct ChecksumWriter<W: Write> {
+    inner: W,
+    hasher: Hasher,
+}
+
+#[cfg(feature = "checksum")]
+impl<W: Write> ChecksumWriter<W> {
+    fn new(inner: W, algo: ChecksumAlgo) -> Self {
+        let hasher = match algo {
+            ChecksumAlgo::Sha256 => Hasher::Sha256(sha2::Sha256::default()),
+            ChecksumAlgo::Crc32 => Hasher::Crc32(crc32fast::Hasher::new()),
+        };
+        ChecksumWriter { inner, hasher }
+    }
+
+    fn hex_digest(self) -> String {
+        use sha2::Digest;
+        match self.hasher {
+            Hasher::Sha256(h) => format!("{:x}", h.finalize()),
+            Hasher::Crc32(h) => format!("{:08x}", h.finalize()),
+        }
+    }
+}
+
+#[cfg(feature = "checksum")]
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        use sha2::Digest;
+        let n = self.inner.write(buf)?;
+        match &mut self.hasher {
+            Hasher::Sha256(h) => h.update(&buf[..n]),
+            Hasher::Crc32(h) => h.update(&buf[..n]),
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(feature = "base64")]
+pub const BASE64_DEFAULT_WRAP: usize = 76;
+
+// Transformation applied to the content produced by `cat()` on its way to
+// the output. Error lines bypass it so they remain readable.
+#[derive(Clone, Copy)]
+pub enum OutputTransform {
+    Identity,
+    // The body of a C string literal
+    CEscape,
+    #[cfg(feature = "base64")]
+    Base64Encode {
+        wrap: usize,
+    },
+    #[cfg(feature = "base64")]
+    Base64Decode,
+}
+
+// Writer that remembers the last byte written through it
+struct LastByteWriter<W: Write> {
+    inner: W,
+    last: Option<u8>,
+}
+
+impl<W: Write> LastByteWriter<W> {
+    fn new(inner: W) -> Self {
+        LastByteWriter { inner, last: None }
+    }
+}
+
+impl<W: Write> Write for LastByteWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        if n > 0 {
+            self.last = Some(buf[n - 1]);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Holds back the last `n` lines written to it, ended by `end`, and writes
+// them once finished. Without `n`, everything is written straight through.
+struct TailWriter<W: Write> {
+    inner: W,
+    n: Option<usize>,
+    end: u8,
+    // The last complete lines, then the one being written if it isn't yet
+    lines: VecDeque<Vec<u8>>,
+}
+
+impl<W: Write> TailWriter<W> {
+    fn new(inner: W, options: &CatOptions) -> Self {
+        let end = match options.record_separator {
+            b'\n' => *options.line_ending.as_bytes().last().unwrap_or(&b'\n'),
+            separator => separator,
+        };
+        TailWriter {
+            inner,
+            n: options.tail_lines,
+            end,
+            lines: VecDeque::new(),
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        for line in self.lines.drain(..) {
+            self.inner.write_all(&line)?;
+        }
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Write for TailWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(n) = self.n else {
+            return self.inner.write(buf);
+        };
+        for piece in buf.split_inclusive(|&b| b == self.end) {
+            // A new line pushes the oldest one out once there are enough,
+            // reusing its buffer
+            if self
+                .lines
+                .back()
+                .is_none_or(|line| line.last() == Some(&self.end))
+            {
+                let mut line = match self.lines.len() >= n {
+                    true => self.lines.pop_front().unwrap_or_default(),
+                    false => Vec::new(),
+                };
+                line.clear();
+                self.lines.push_back(line);
+            }
+            if let Some(line) = self.lines.back_mut() {
+                line.extend_from_slice(piece);
+            }
+        }
+        if n == 0 {
+            self.lines.clear();
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Lets through `limit` bytes at most, failing with `OutputLimitReached`
+// once they're used up
+struct LimitWriter<W: Write> {
+    inner: W,
+    remaining: Option<u64>,
+}
+
+impl<W: Write> LimitWriter<W> {
+    fn new(inner: W, limit: Option<u64>) -> Self {
+        LimitWriter {
+            inner,
+            remaining: limit,
+        }
+    }
+}
+
+impl<W: Write> Write for LimitWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(remaining) = self.remaining else {
+            return self.inner.write(buf);
+        };
+        if remaining == 0 && !buf.is_empty() {
+            return Err(io::Error::other(OutputLimitReached));
+        }
+        let len = remaining.min(buf.len() as u64) as usize;
+        let n = self.inner.write(&buf[..len])?;
+        self.remaining = Some(remaining - n as u64);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[derive(Debug)]
+struct OutputLimitReached;
+
+impl Display for OutputLimitReached {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "output limit reached")
+    }
+}
+
+impl Error for OutputLimitReached {}
+
+struct ContentWriter<W: Write> {
+    out: W,
+    transform: OutputTransform,
+    tabstop: Option<usize>,
+    // Column the next character lands in, for expanding tabs
+    line_column: usize,
+    // Whether escaped newlines are followed by real ones
+    keep_newlines: bool,
+    // Bytes held back until a full base64 group is available
+    #[cfg(feature = "base64")]
+    pending: Vec<u8>,
+    #[cfg(feature = "base64")]
+    column: usize,
+    #[cfg(feature = "base64")]
+    padded: bool,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> ContentWriter<W> {
+    fn new(out: W, options: &CatOptions) -> Self {
+        ContentWriter {
+            out,
+            transform: options.transform,
+            tabstop: options.tabstop,
+            line_column: 0,
+            keep_newlines: options.max_line_width.is_some(),
+            #[cfg(feature = "base64")]
+            pending: Vec::new(),
+            #[cfg(feature = "base64")]
+            column: 0,
+            #[cfg(feature = "base64")]
+            padded: false,
+            error: None,
+        }
+    }
+
+    // Writer for output that must not be transformed, e.g. error lines.
+    // Those always end their line, so the next content starts a new one.
+    fn raw(&mut self) -> &mut W {
+        self.line_column = 0;
+        &mut self.out
+    }
+
+    // Returns the error hit while transforming content, if any, and resets
+    // the transform so that the next input starts afresh
+    fn take_error(&mut self) -> Option<io::Error> {
+        let e = self.error.take()?;
+        #[cfg(feature = "base64")]
+        {
+            self.pending.clear();
+            self.padded = false;
+        }
+        Some(e)
+    }
+
+    // Flushes whatever the transform is still holding back
+    fn finish(&mut self) -> io::Result<()> {
+        match self.transform {
+            OutputTransform::Identity | OutputTransform::CEscape => {}
+            #[cfg(feature = "base64")]
+            OutputTransform::Base64Encode { .. } => {
+                let encoded = BASE64.encode(&self.pending);
+                self.pending.clear();
+                self.write_wrapped(encoded.as_bytes())?;
+                if self.column > 0 {
+                    writeln!(self.out)?;
+                    self.column = 0;
+                }
+            }
+            #[cfg(feature = "base64")]
+            OutputTransform::Base64Decode => {
+                if !self.pending.is_empty() {
+                    let pending = std::mem::take(&mut self.pending);
+                    self.decode(&pending)?;
+                }
+            }
+        }
+        self.out.flush()
+    }
+
+    #[cfg(feature = "base64")]
+    fn write_wrapped(&mut self, mut encoded: &[u8]) -> io::Result<()> {
+        let OutputTransform::Base64Encode { wrap } = self.transform else {
+            unreachable!()
+        };
+        if wrap == 0 {
+            return self.out.write_all(encoded);
+        }
+        while !encoded.is_empty() {
+            let n = (wrap - self.column).min(encoded.len());
+            self.out.write_all(&encoded[..n])?;
+            self.column += n;
+            encoded = &encoded[n..];
+            if self.column == wrap {
+                writeln!(self.out)?;
+                self.column = 0;
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "base64")]
+    fn decode(&mut self, quanta: &[u8]) -> io::Result<()> {
+        match BASE64.decode(quanta) {
+            Ok(decoded) => {
+                self.padded = quanta.ends_with(b"=");
+                self.out.write_all(&decoded)
+            }
+            Err(e) => {
+                self.error = Some(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid base64 input: {e}"),
+                ));
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<W: Write> Write for ContentWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.tabstop {
+            Some(tabstop) => {
+                let expanded = self.expand_tabs(buf, tabstop);
+                self.write_transformed(&expanded)?;
+            }
+            None => self.write_transformed(buf)?,
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+impl<W: Write> ContentWriter<W> {
+    // Replaces each tab with spaces up to the next tab stop, counting
+    // columns in UTF-8 scalars
+    fn expand_tabs(&mut self, buf: &[u8], tabstop: usize) -> Vec<u8> {
+        let mut expanded = Vec::with_capacity(buf.len());
+        for &b in buf {
+            match b {
+                b'\t' => {
+                    let spaces = tabstop - self.line_column % tabstop;
+                    expanded.resize(expanded.len() + spaces, b' ');
+                    self.line_column += spaces;
+                }
+                b'\n' | b'\r' => {
+                    expanded.push(b);
+                    self.line_column = 0;
+                }
+                _ => {
+                    expanded.push(b);
+                    // Continuation bytes don't start a new character
+                    if b & 0xC0 != 0x80 {
+                        self.line_column += 1;
+                    }
+                }
+            }
+        }
+        expanded
+    }
+
+    fn write_transformed(&mut self, buf: &[u8]) -> io::Result<()> {
+        match self.transform {
+            OutputTransform::Identity => self.out.write_all(buf),
+            OutputTransform::CEscape => {
+                let mut escaped = Vec::with_capacity(buf.len());
+                for &b in buf {
+                    c_escape(b, &mut escaped);
+                    if b == b'\n' && self.keep_newlines {
+                        escaped.push(b'\n');
+                    }
+                }
+                self.out.write_all(&escaped)
+            }
+            #[cfg(feature = "base64")]
+            OutputTransform::Base64Encode { .. } => {
+                self.pending.extend_from_slice(buf);
+                let full = self.pending.len() / 3 * 3;
+                let encoded = BASE64.encode(&self.pending[..full]);
+                self.pending.drain(..full);
+                self.write_wrapped(encoded.as_bytes())
+            }
+            #[cfg(feature = "base64")]
+            OutputTransform::Base64Decode => {
+                if self.error.is_some() {
+                    return Ok(());
+                }
+                for &b in buf.iter().filter(|b| !b.is_ascii_whitespace()) {
+                    if self.padded {
+                        self.error = Some(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "invalid base64 input: data after padding",
+                        ));
+                        return Ok(());
+                    }
+                    self.pending.push(b);
+                }
+                let full = self.pending.len() / 4 * 4;
+                let quanta = self.pending.drain(..full).collect::<Vec<_>>();
+                if !quanta.is_empty() {
+                    self.decode(&quanta)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// Printable ASCII stays as it is, save for quotes and backslashes. Other
+// bytes become three octal digits, which unlike `\x` can't run on into a
+// digit after them.
+fn c_escape(b: u8, out: &mut Vec<u8>) {
+    match b {
+        b'\n' => out.extend_from_slice(b"\\n"),
+        b'\t' => out.extend_from_slice(b"\\t"),
+        b'\r' => out.extend_from_slice(b"\\r"),
+        b'"' => out.extend_from_slice(b"\\\""),
+        b'\\' => out.extend_from_slice(b"\\\\"),
+        b' '..=b'~' => out.push(b),
+        _ => out.extend_from_slice(format!("\\{b:03o}").as_bytes()),
+    }
+}
+
+// Which case letters in the content are folded to
+#[derive(Clone, Copy, PartialEq)]
+pub enum CaseFold {
+    Lower,
+    Upper,
+}
+
+// What numbering does past the highest number allowed
+#[derive(Clone, Copy, PartialEq)]
+pub enum CapMode {
+    // Start again from 1
+    Wrap,
+    // Leave the gutter blank
+    Stop,
+}
+
+#[derive(Clone, Copy)]
+pub enum WrapMode {
+    // Cut lines off at the limit, optionally marking the cut with `…`
+    Truncate { ellipsis: bool },
+    // Break lines onto as many rows as they need
+    Wrap,
+    // Like `Wrap`, but break rows after their last space or tab where there
+    // is one, like `fold -s`
+    Word,
+}
+
+// What lines are matched against, without their newline
+#[derive(Clone)]
+pub enum LinePattern {
+    Substring(String),
+    #[cfg(feature = "regex")]
+    Regex(regex::bytes::Regex),
+}
+
+impl LinePattern {
+    fn is_match(&self, line: &[u8]) -> bool {
+        match self {
+            LinePattern::Substring(s) if s.is_empty() => true,
+            LinePattern::Substring(s) => line.windows(s.len()).any(|w| w == s.as_bytes()),
+            #[cfg(feature = "regex")]
+            LinePattern::Regex(re) => re.is_match(line),
+        }
+    }
+}
+
+// How line numbers are laid out before the content
+#[derive(Clone, PartialEq)]
+pub enum NumberStyle {
+    // Right-justified in `line_number_width` columns and followed by a tab,
+    // like GNU cat
+    GnuRight,
+    // Flush left and followed by `: `, with no padding
+    LeftColon,
+    // Justified either way in `line_number_width` columns and followed by
+    // `separator`
+    Custom { separator: String, left_align: bool },
+}
+
+// Radix line numbers are written in
+#[derive(Clone, Copy, PartialEq)]
+pub enum NumberFormat {
+    Dec,
+    Hex,
+    Oct,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl LineEnding {
+    fn as_bytes(self) -> &'static [u8] {
+        match self {
+            LineEnding::Lf => b"\n",
+            LineEnding::CrLf => b"\r\n",
+            LineEnding::Cr => b"\r",
+        }
+    }
+
+    // CRLF if more lines in `chunk` end with it than with a bare newline
+    fn dominant(chunk: &[u8]) -> Self {
+        let newlines = chunk.iter().filter(|&&b| b == b'\n').count();
+        let crlfs = chunk.windows(2).filter(|w| w == b"\r\n").count();
+        if crlfs > newlines - crlfs {
+            LineEnding::CrLf
+        } else {
+            LineEnding::Lf
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    // ISO-8859-1, transcoded to UTF-8 on the way in
+    Latin1,
+}
+
+// Reader that converts the bytes of another as they're read
+struct MapReader<R, F> {
+    inner: R,
+    map: F,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl<R, F> MapReader<R, F>
+where
+    R: BufRead,
+    F: FnMut(&[u8], &mut Vec<u8>),
+{
+    fn new(inner: R, map: F) -> Self {
+        MapReader {
+            inner,
+            map,
+            buf: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+impl<R, F> io::Read for MapReader<R, F>
+where
+    R: BufRead,
+    F: FnMut(&[u8], &mut Vec<u8>),
+{
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(out.len());
+        out[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R, F> BufRead for MapReader<R, F>
+where
+    R: BufRead,
+    F: FnMut(&[u8], &mut Vec<u8>),
+{
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // A chunk may map to nothing, so keep going until there's output
+        // or the inner reader is exhausted
+        while self.pos == self.buf.len() {
+            self.buf.clear();
+            self.pos = 0;
+            let chunk = self.inner.fill_buf()?;
+            if chunk.is_empty() {
+                break;
+            }
+            (self.map)(chunk, &mut self.buf);
+            let n = chunk.len();
+            self.inner.consume(n);
+        }
+        Ok(&self.buf[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+}
+
+fn latin1_to_utf8(chunk: &[u8], out: &mut Vec<u8>) {
+    for &b in chunk {
+        if b < 0x80 {
+            out.push(b);
+        } else {
+            out.extend_from_slice(&[0xC0 | b >> 6, 0x80 | b & 0x3F]);
+        }
+    }
+}
+
+type ChunkMap = Box<dyn FnMut(&[u8], &mut Vec<u8>)>;
+
+// An input as it's read once the conversions that have to happen before
+// its content is split into lines are applied
+enum Decoded<R> {
+    Raw(R),
+    Mapped(MapReader<Box<Decoded<R>>, ChunkMap>),
+}
+
+impl<R: BufRead> io::Read for Decoded<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Decoded::Raw(reader) => reader.read(out),
+            Decoded::Mapped(reader) => reader.read(out),
+        }
+    }
+}
+
+impl<R: BufRead> BufRead for Decoded<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        match self {
+            Decoded::Raw(reader) => reader.fill_buf(),
+            Decoded::Mapped(reader) => reader.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        match self {
+            Decoded::Raw(reader) => reader.consume(amt),
+            Decoded::Mapped(reader) => reader.consume(amt),
+        }
+    }
+}
+
+// Wraps the reader in the conversions that have to happen before its
+// content is split into lines
+fn decode_input<R: BufRead>(reader: R, options: &CatOptions) -> Decoded<R> {
+    let mut reader = Decoded::Raw(reader);
+    if !options.replace_bytes.is_empty() {
+        let replacements = options.replace_bytes.clone();
+        let map: ChunkMap = Box::new(move |chunk, out| replace_bytes(chunk, &replacements, out));
+        reader = Decoded::Mapped(MapReader::new(Box::new(reader), map));
+    }
+    if options.input_encoding == Encoding::Latin1 {
+        let map: ChunkMap = Box::new(latin1_to_utf8);
+        reader = Decoded::Mapped(MapReader::new(Box::new(reader), map));
+    }
+    reader
+}
+
+// Newlines are left alone whatever they're mapped to, as lines are split on
+// them afterwards
+fn replace_bytes(chunk: &[u8], replacements: &HashMap<u8, Vec<u8>>, out: &mut Vec<u8>) {
+    for b in chunk {
+        match replacements.get(b) {
+            Some(replacement) if *b != b'\n' => out.extend_from_slice(replacement),
+            _ => out.push(*b),
+        }
+    }
+}
+
+// Reader taking lines from two others in turn, carrying on with the longer
+// one once the shorter runs out. A last line without a newline gets one if
+// more lines follow it.
+struct Interleaved<R> {
+    inputs: [R; 2],
+    exhausted: [bool; 2],
+    turn: usize,
+    unterminated: bool,
+    line: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: BufRead> Interleaved<R> {
+    fn new(first: R, second: R) -> Self {
+        Interleaved {
+            inputs: [first, second],
+            exhausted: [false; 2],
+            turn: 0,
+            unterminated: false,
+            line: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    // The input whose turn it is, skipping those that have run out
+    fn next_input(&mut self) -> Option<usize> {
+        let i = self.turn;
+        if !self.exhausted[i] {
+            self.turn = 1 - i;
+            Some(i)
+        } else if !self.exhausted[1 - i] {
+            Some(1 - i)
+        } else {
+            None
+        }
+    }
+}
+
+impl<R: BufRead> io::Read for Interleaved<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(out.len());
+        out[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for Interleaved<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.pos == self.line.len() {
+            let Some(i) = self.next_input() else {
+                break;
+            };
+            self.line.clear();
+            self.pos = 0;
+            if self.inputs[i].read_until(b'\n', &mut self.line)? == 0 {
+                self.exhausted[i] = true;
+                continue;
+            }
+            if self.unterminated {
+                self.line.insert(0, b'\n');
+            }
+            self.unterminated = self.line.last() != Some(&b'\n');
+        }
+        Ok(&self.line[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+}
+
+// Opens the input, or returns None if it's to be skipped
+fn open_input<E: Write>(
+    input: InputSource,
+    options: &CatOptions,
+    err: &mut E,
+) -> io::Result<Option<OpenedInput>> {
+    if let InputSource::File(path) = &input {
+        let metadata = match fs::metadata(path) {
+            Err(e) if options.ignore_missing && e.kind() == io::ErrorKind::NotFound => {
+                return Ok(None);
+            }
+            metadata => metadata.ok(),
+        };
+        if let (Some(max), Some(metadata)) = (options.max_file_size, &metadata) {
+            if metadata.is_file() && metadata.len() > max {
+                let error_prefix = &options.error_prefix;
+                let size = metadata.len();
+                writeln!(err, "{error_prefix}: {path}: file too large ({size} bytes)")?;
+                return Ok(None);
+            }
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::FileTypeExt;
+            if metadata.is_some_and(|m| m.file_type().is_fifo()) {
+                return open_fifo(path, options, err);
+            }
+        }
+    }
+    #[cfg(unix)]
+    if let (InputSource::StdIn, Some(timeout)) = (&input, options.input_timeout) {
+        let reader = TimeoutReader::new(io::stdin(), timeout);
+        return Ok(Some(Ok(Input::stdin(Box::new(BufReader::new(reader))))));
+    }
+    Ok(Some(input.get_buf_read()))
+}
+
+// Opening a FIFO waits for something to write to it, which is worth a
+// warning. Without blocking, a FIFO with nothing to read yet is skipped.
+#[cfg(unix)]
+fn open_fifo<E: Write>(
+    path: &str,
+    options: &CatOptions,
+    err: &mut E,
+) -> io::Result<Option<OpenedInput>> {
+    use std::os::{fd::AsRawFd, unix::fs::OpenOptionsExt};
+
+    let error_prefix = &options.error_prefix;
+    if !options.no_block {
+        writeln!(err, "{error_prefix}: {path}: is a FIFO, waiting for writer")?;
+        err.flush()?;
+        if options.input_timeout.is_none() {
+            return Ok(Some(InputSource::File(path.to_string()).get_buf_read()));
+        }
+    }
+
+    // Opened without blocking, so that no writer is either skipped or,
+    // with a timeout, left to time out like one that writes nothing
+    let file = match fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+    {
+        Ok(file) => file,
+        Err(e) => {
+            let e = io::Error::new(e.kind(), format!("{path}: {e}"));
+            return Ok(Some(Err(e.into())));
+        }
+    };
+    let fd = file.as_raw_fd();
+    if options.no_block {
+        let mut poll = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: `poll` is a single valid pollfd for an open descriptor
+        let ready = unsafe { libc::poll(&mut poll, 1, 0) };
+        if ready <= 0 || poll.revents & libc::POLLIN == 0 {
+            writeln!(
+                err,
+                "{error_prefix}: {path}: is a FIFO with nothing to read, skipping"
+            )?;
+            err.flush()?;
+            return Ok(None);
+        }
+    }
+
+    // Go back to waiting for whatever a writer writes
+    // SAFETY: `fd` stays open for as long as `file` does
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        libc::fcntl(fd, libc::F_SETFL, flags & !libc::O_NONBLOCK);
+    }
+    let reader: Box<dyn BufRead> = match options.input_timeout {
+        Some(timeout) => Box::new(BufReader::new(TimeoutReader::new(file, timeout))),
+        None => Box::new(BufReader::new(file)),
+    };
+    Ok(Some(Ok(Input::new(path, reader))))
+}
+
+// Reader failing with a timeout when waiting longer than `timeout` for
+// something to read
+#[cfg(unix)]
+struct TimeoutReader<R> {
+    inner: R,
+    timeout: Duration,
+}
+
+#[cfg(unix)]
+impl<R> TimeoutReader<R> {
+    fn new(inner: R, timeout: Duration) -> Self {
+        TimeoutReader { inner, timeout }
+    }
+}
+
+#[cfg(unix)]
+impl<R: Read + std::os::fd::AsRawFd> io::Read for TimeoutReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut poll = libc::pollfd {
+            fd: self.inner.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let millis = self.timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+        loop {
+            // SAFETY: `poll` is a single valid pollfd for an open descriptor
+            return match unsafe { libc::poll(&mut poll, 1, millis) } {
+                -1 => match io::Error::last_os_error() {
+                    // A signal cut the wait short, so wait again
+                    e if e.kind() == io::ErrorKind::Interrupted => continue,
+                    e => Err(e),
+                },
+                0 => Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("no input for {:?}", self.timeout),
+                )),
+                _ => self.inner.read(buf),
+            };
+        }
+    }
+}
+
+// Standard input can only be read through once, so only its first mention
+// is kept, with a warning about each of the others
+fn drop_reused_stdin<E: Write>(
+    inputs: Vec<InputSource>,
+    err: &mut E,
+    options: &CatOptions,
+) -> io::Result<Vec<InputSource>> {
+    let mut seen_stdin = false;
+    let mut kept = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        if input == InputSource::StdIn {
+            if seen_stdin {
+                writeln!(err, "{}: {STDIN_NAME} reused", options.error_prefix)?;
+                continue;
+            }
+            seen_stdin = true;
+        }
+        kept.push(input);
+    }
+    Ok(kept)
+}
+
+// Combines two opened inputs into one interleaving their lines. If either
+// couldn't be opened, there's nothing to interleave, and both are left as
+// they are.
+fn interleave(mut ins: Vec<OpenedInput>) -> io::Result<Vec<OpenedInput>> {
+    if ins.len() != 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "interleaving takes exactly two inputs",
+        ));
+    }
+    let second = ins.pop().unwrap();
+    let first = ins.pop().unwrap();
+    Ok(match (first, second) {
+        (Ok(first), Ok(second)) => vec![Ok(Input {
+            path: None,
+            reader: Box::new(Interleaved::new(first.reader, second.reader)),
+        })],
+        (first, second) => vec![first, second],
+    })
+}
+
+// An input as it's passed to the hooks of `Concatenator`
+#[derive(Debug)]
+pub struct InputDesc {
+    // Position of the input among all the inputs
+    pub index: usize,
+    // None for standard input and readers
+    pub path: Option<String>,
+}
+
+// What was read from one input, and how long it took
+#[derive(Debug)]
+pub struct InputStats {
+    pub bytes: u64,
+    pub lines: usize,
+    pub elapsed: Duration,
+}
+
+type StartHook = Box<dyn FnMut(&InputDesc)>;
+type EndHook = Box<dyn FnMut(&InputDesc, InputStats)>;
+type LineTransform = Box<dyn for<'a> FnMut(&'a [u8]) -> Cow<'a, [u8]>>;
+type LineFilter = Box<dyn FnMut(&[u8]) -> bool>;
+
+// Callbacks for each input that's read, as it starts and once it ends, and
+// for each line read from it
+#[derive(Default)]
+struct InputHooks {
+    start: Option<StartHook>,
+    end: Option<EndHook>,
+    transform: Option<LineTransform>,
+    filter: Option<LineFilter>,
+}
+
+impl InputHooks {
+    fn started(&mut self, desc: &InputDesc) {
+        if let Some(start) = &mut self.start {
+            start(desc);
+        }
+    }
+
+    fn ended(&mut self, desc: &InputDesc, stats: InputStats) {
+        if let Some(end) = &mut self.end {
+            end(desc, stats);
+        }
+    }
+}
+
+// What `cat()` read. Lines and bytes are counted after decoding the input.
+#[derive(Debug, Default)]
+pub struct CatStats {
+    pub files: usize,
+    pub lines: usize,
+    pub bytes: u64,
+    pub errors: Vec<CatError>,
+    // Whether writing stopped at `max_total_bytes`
+    pub output_limit_reached: bool,
+    // Whether the last byte written was a newline, for callers deciding
+    // whether to separate what they write next
+    pub ended_with_newline: bool,
+    // The number the next line would be given, to carry numbering on from
+    pub next_line_number: usize,
+    // The hex digest of the output, when `with_checksum()` asked for one
+    #[cfg(feature = "checksum")]
+    pub checksum: Option<String>,
+}
+
+// Summarizes the stats as e.g. `3 files, 8 lines, 212 bytes, 1 error`
+impl Display for CatStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let plural = |n: u64, what: &str| match n {
+            1 => format!("1 {what}"),
+            _ => format!("{n} {what}s"),
+        };
+        write!(
+            f,
+            "{}, {}, {}, {}",
+            plural(self.files as u64, "file"),
+            plural(self.lines as u64, "line"),
+            plural(self.bytes, "byte"),
+            plural(self.errors.len() as u64, "error")
+        )
+    }
+}
+
+// An input that couldn't be read, in full or at all
+#[derive(Debug)]
+pub struct CatError {
+    // Position of the input among all the inputs
+    pub input: usize,
+    pub message: String,
+}
+
+impl CatError {
+    fn new(input: usize, e: impl Display) -> Self {
+        CatError {
+            input,
+            message: e.to_string(),
+        }
+    }
+}
+
+impl Display for CatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for CatError {}
+
+// Longest piece of a line read in one go
+const MAX_LINE_CHUNK: u64 = 64 * 1024;
+
+#[derive(PartialEq)]
+enum BufReadState {
+    StartOfLine,
+    MiddleOfLine,
+}
+
+// Content and errors are written to `out`, while warnings go to `err`.
+// Returns what was read, and the errors of the inputs that failed in the
+// order they failed.
+fn cat<R, W, E>(
+    ins: Vec<Result<Input<R>, Box<dyn Error>>>,
+    out: &mut W,
+    err: &mut E,
+    options: &CatOptions,
+) -> io::Result<CatStats>
+where
+    R: BufRead,
+    W: Write,
+    E: Write,
+{
+    cat_hooked(ins, out, err, options, InputHooks::default())
+}
+
+// Whatever reads the output stopped reading it, as `head` does, so there's
+// no one left to write the rest for
+fn ignore_broken_pipe(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
+
+// Like `cat()`, calling `hooks` as each input starts and ends
+fn cat_hooked<R, W, E>(
+    ins: Vec<Result<Input<R>, Box<dyn Error>>>,
+    out: &mut W,
+    err: &mut E,
+    options: &CatOptions,
+    hooks: InputHooks,
+) -> io::Result<CatStats>
+where
+    R: BufRead,
+    W: Write,
+    E: Write,
+{
+    let mut sink = io::sink();
+    let mut err: &mut dyn Write = if options.quiet { &mut sink } else { err };
+    let mut records = Records::new(ins, out, options, hooks)?;
+    if let Err(e) = records.run(&mut err) {
+        records.stop(e, &mut err)?;
+    }
+    Ok(records.stats)
+}
+
+// Catting in progress, ready to go on a record at a time: the inputs still
+// to read, the writers records go through on their way out, and what's
+// carried over from one record to the next
+struct Records<R, W: Write> {
+    ins: iter::Enumerate<vec::IntoIter<OpenedInput<R>>>,
+    last_input: usize,
+    // The input being read, if one is
+    input: Option<OpenInput<R>>,
+    out: ContentWriter<LastByteWriter<TailWriter<LimitWriter<W>>>>,
+    options: CatOptions,
+    hooks: InputHooks,
+    stats: CatStats,
+    // Whether the output was started yet, and whether it's been ended
+    started: bool,
+    ended: bool,
+    line_count: usize,
+    buf_read_state: BufReadState,
+    // Content of the current line, held back when it can only be written
+    // once the whole line has been seen
+    hold_lines: bool,
+    held: HeldLine,
+    warned_too_long: bool,
+    line: Vec<u8>,
+    // The last whole line written, for dropping the same one after it
+    previous_line: Option<Vec<u8>>,
+    // Whether an input had content yet, to separate the next one from
+    had_content: bool,
+    // Whether nothing has been written yet, and the blank lines that might
+    // turn out to end the output
+    at_start: bool,
+    blank_lines: Vec<u8>,
+}
+
+// The input being read, and what's been seen of it so far
+struct OpenInput<R> {
+    desc: InputDesc,
+    reader: Decoded<R>,
+    start: Instant,
+    start_bytes: u64,
+    start_lines: usize,
+    // Which line endings this input has used, and whether the line so far
+    // ends with a carriage return
+    seen_lf: bool,
+    seen_crlf: bool,
+    ends_with_cr: bool,
+    ansi: AnsiStripper,
+    first_chunk: bool,
+}
+
+impl<R> OpenInput<R> {
+    fn stats(&self, stats: &CatStats) -> InputStats {
+        InputStats {
+            bytes: stats.bytes - self.start_bytes,
+            lines: stats.lines - self.start_lines,
+            elapsed: self.start.elapsed(),
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> Records<R, W> {
+    fn new(
+        ins: Vec<Result<Input<R>, Box<dyn Error>>>,
+        out: W,
+        options: &CatOptions,
+        hooks: InputHooks,
+    ) -> io::Result<Self> {
+        if options.first_line_number == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "line numbers start at 1 or more",
+            ));
+        }
+        if options.tail_lines.is_some() && options.newline_replacement.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "joined lines can't be told apart for the tail",
+            ));
+        }
+        let mut ins = ins;
+        let options = match ins.iter_mut().find_map(|input| input.as_mut().ok()) {
+            Some(first) if options.preserve_dominant_ending => {
+                let chunk = first.reader.fill_buf()?;
+                CatOptions {
+                    line_ending: LineEnding::dominant(chunk),
+                    ..options.clone()
+                }
+            }
+            _ => options.clone(),
+        };
+
+        let hold_lines = options.reverse_lines
+            || options.trim_trailing_whitespace
+            || options.max_line_width.is_some()
+            || options.syntax_highlight
+            || options.format != OutputFormat::Text;
+        let out = LimitWriter::new(out, options.max_total_bytes);
+        let out = LastByteWriter::new(TailWriter::new(out, &options));
+        let out = ContentWriter::new(out, &options);
+        Ok(Records {
+            last_input: ins.len().saturating_sub(1),
+            ins: ins.into_iter().enumerate(),
+            input: None,
+            out,
+            hooks,
+            stats: CatStats {
+                next_line_number: options.first_line_number,
+                ..Default::default()
+            },
+            started: false,
+            ended: false,
+            line_count: options.first_line_number,
+            buf_read_state: BufReadState::StartOfLine,
+            hold_lines,
+            held: HeldLine::default(),
+            warned_too_long: false,
+            line: Vec::new(),
+            previous_line: None,
+            had_content: false,
+            at_start: true,
+            blank_lines: Vec::new(),
+            options,
+        })
+    }
+
+    // The writer the records end up in
+    fn output(&mut self) -> &mut W {
+        &mut self.out.out.inner.inner.inner
+    }
+
+    fn run<E: Write>(&mut self, err: &mut E) -> io::Result<()> {
+        while self.step(err)? {}
+        Ok(())
+    }
+
+    // Writes the next record, starting the output before the first and
+    // ending it once the inputs run out. Returns whether there's more to
+    // write.
+    fn step<E: Write>(&mut self, err: &mut E) -> io::Result<bool> {
+        if self.ended {
+            return Ok(false);
+        }
+        if !std::mem::replace(&mut self.started, true) {
+            self.start()?;
+        } else if self.input.is_some() {
+            self.read_record(err)?;
+        } else if let Some((index, input)) = self.ins.next() {
+            self.open(index, input, err)?;
+        } else {
+            self.ended = true;
+            self.end(err)?;
+        }
+        Ok(!self.ended)
+    }
+
+    // Ends the catting early on an error, which only fails it if there's
+    // more to the error than the output being closed or full
+    fn stop<E: Write>(&mut self, e: io::Error, err: &mut E) -> io::Result<()> {
+        self.ended = true;
+        // Whatever reads the output stopped reading it, as `head` does, so
+        // there's no one left to write it for
+        if e.kind() == io::ErrorKind::BrokenPipe {
+            return Ok(());
+        }
+        if !e.get_ref().is_some_and(|e| e.is::<OutputLimitReached>()) {
+            return Err(e);
+        }
+        // Everything up to the limit was written, and nothing more will be.
+        // The lines held back for the tail are still written, as far as the
+        // limit lets them.
+        let tail = &mut self.out.out.inner;
+        let _ = tail.finish();
+        self.stats.output_limit_reached = true;
+        ignore_broken_pipe(tail.inner.inner.flush())?;
+        writeln!(err, "{}: {e}", self.options.error_prefix)?;
+        err.flush()
+    }
+
+    fn start(&mut self) -> io::Result<()> {
+        if let Some(prefix) = &self.options.prefix {
+            self.out.write_all(prefix.as_bytes())?;
+        }
+        #[cfg(feature = "serde_json")]
+        if self.options.format == OutputFormat::Json {
+            self.out.write_all(b"[")?;
+        }
+        Ok(())
+    }
+
+    // Starts reading the next input, or reports why it can't be read
+    fn open<E: Write>(
+        &mut self,
+        index: usize,
+        input: Result<Input<R>, Box<dyn Error>>,
+        err: &mut E,
+    ) -> io::Result<()> {
+        use BufReadState::*;
+
+        let options = &self.options;
+        let error_prefix = &options.error_prefix;
+        let out = &mut self.out;
+        let Input { path, mut reader } = match input {
+            Ok(input) => input,
+            Err(e) => {
+                if self.buf_read_state == MiddleOfLine {
+                    end_held_line(out, &mut self.held, options, false)?;
+                }
+                if options.strict {
+                    out.flush()?;
+                    return Err(match e.downcast::<io::Error>() {
+                        Ok(e) => *e,
+                        Err(e) => io::Error::other(e.to_string()),
+                    });
+                }
+                self.previous_line = None;
+                out.write_all(&self.blank_lines)?;
+                self.blank_lines.clear();
+                self.at_start = false;
+                let error = CatError::new(index, e);
+                report_error(
+                    error_out(out.raw(), err, options),
+                    options,
+                    &mut self.stats,
+                    error,
+                )?;
+                out.flush()?;
+                if !options.number_blank_as_continuation {
+                    self.buf_read_state = StartOfLine;
+                }
+                return Ok(());
+            }
+        };
+
+        if options.verbose {
+            let name = path.as_deref().unwrap_or(STDIN_NAME);
+            writeln!(err, "{error_prefix}: reading {name}")?;
+            err.flush()?;
+        }
+
+        // Skip binaries, going by whether their start has a NUL byte
+        if options.skip_binary
+            && !options.force
+            && peek_first_bytes(&mut reader, BINARY_SNIFF_LEN)?.contains(&0)
+        {
+            let name = path.as_deref().unwrap_or(STDIN_NAME);
+            writeln!(err, "{error_prefix}: {name}: binary file, skipping")?;
+            err.flush()?;
+            return Ok(());
+        }
+
+        let desc = InputDesc { index, path };
+        self.hooks.started(&desc);
+
+        #[cfg(feature = "syntect")]
+        {
+            self.held.highlighter = match &desc.path {
+                Some(path) if options.syntax_highlight => highlighter_for(path),
+                _ => None,
+            };
+        }
+
+        // Restart numbering for each input. If the previous input didn't end
+        // with a newline, our first line was merged into its last one.
+        if options.per_file_numbering {
+            self.line_count = if self.buf_read_state == MiddleOfLine {
+                2
+            } else {
+                1
+            };
+        }
+
+        self.input = Some(OpenInput {
+            desc,
+            reader: decode_input(reader, options),
+            start: Instant::now(),
+            start_bytes: self.stats.bytes,
+            start_lines: self.stats.lines,
+            seen_lf: false,
+            seen_crlf: false,
+            ends_with_cr: false,
+            ansi: AnsiStripper::default(),
+            first_chunk: true,
+        });
+        self.stats.files += 1;
+        Ok(())
+    }
+
+    // Writes the next record of the current input, closing the input once
+    // it runs out
+    fn read_record<E: Write>(&mut self, err: &mut E) -> io::Result<()> {
+        use BufReadState::*;
+
+        let Records {
+            input: current,
+            out,
+            options,
+            hooks,
+            stats,
+            line_count,
+            buf_read_state,
+            hold_lines,
+            held,
+            warned_too_long,
+            line,
+            previous_line,
+            had_content,
+            at_start,
+            blank_lines,
+            ..
+        } = self;
+        let Some(input) = current else {
+            return Ok(());
+        };
+        let (options, hold_lines) = (&*options, *hold_lines);
+        let error_prefix = &options.error_prefix;
+        let index = input.desc.index;
+        let path = &input.desc.path;
+
+        // Read up to and including the next newline. Lines longer than the
+        // cap are read a piece at a time, to keep memory bounded. When the
+        // whole line is needed to decide whether to number, rewrite or drop
+        // it, the cap is the longest line held whole instead. Either way no
+        // more is read than could be written, so that an endless input like
+        // `/dev/urandom` can't be buffered without end.
+        line.clear();
+        let whole_lines = options.number_matching.is_some()
+            || options.unique_adjacent
+            || hooks.transform.is_some()
+            || hooks.filter.is_some();
+        let cap = match whole_lines {
+            true => (options.max_buffered_line as u64).saturating_add(1),
+            false => MAX_LINE_CHUNK,
+        };
+        let cap = match options.max_total_bytes {
+            Some(max) => cap.min(max.saturating_add(1).max(MAX_LINE_CHUNK)),
+            None => cap,
+        };
+        let read = (&mut input.reader)
+            .take(cap)
+            .read_until(options.record_separator, line);
+
+        // An input that opened but can't be read, like a directory, is
+        // reported like one that couldn't be opened, and catting goes on
+        // with the next
+        let bytes_read = match read {
+            Ok(bytes_read) => bytes_read,
+            Err(e) if options.strict => {
+                out.flush()?;
+                return Err(e);
+            }
+            Err(e) => {
+                if *buf_read_state == MiddleOfLine {
+                    end_held_line(out, held, options, false)?;
+                }
+                *previous_line = None;
+                out.write_all(blank_lines)?;
+                blank_lines.clear();
+                *at_start = false;
+                let name = path.as_deref().unwrap_or(STDIN_NAME);
+                let error = CatError::new(index, format!("{name}: {e}"));
+                report_error(error_out(out.raw(), err, options), options, stats, error)?;
+                out.flush()?;
+                if !options.number_blank_as_continuation {
+                    *buf_read_state = StartOfLine;
+                }
+                hooks.ended(&input.desc, input.stats(stats));
+                *current = None;
+                return Ok(());
+            }
+        };
+
+        // The input is exhausted
+        if bytes_read == 0 {
+            hooks.ended(&input.desc, input.stats(stats));
+            if options.warn_mixed_endings && input.seen_lf && input.seen_crlf {
+                let name = path.as_deref().unwrap_or(STDIN_NAME);
+                writeln!(err, "{error_prefix}: {name}: mixed line endings")?;
+                err.flush()?;
+            }
+            *current = None;
+            return Ok(());
+        }
+
+        // Separate the input from the last one with content, once it turns
+        // out to have some too, on a line of its own
+        if std::mem::take(&mut input.first_chunk) {
+            let separator = options.file_separator.as_ref().filter(|_| *had_content);
+            if *buf_read_state == MiddleOfLine
+                && (separator.is_some() || options.force_line_break_between_files)
+            {
+                end_held_line(out, held, options, true)?;
+                *buf_read_state = StartOfLine;
+                if options.per_file_numbering {
+                    *line_count = 1;
+                }
+            }
+            if let Some(separator) = separator {
+                out.write_all(blank_lines)?;
+                blank_lines.clear();
+                let out = out.raw();
+                out.write_all(separator.as_bytes())?;
+                out.write_all(options.line_ending.as_bytes())?;
+            }
+            *had_content = true;
+        }
+        stats.bytes += bytes_read as u64;
+        if *buf_read_state == StartOfLine {
+            stats.lines += 1;
+        }
+
+        // A line too long to hold whole is written as it comes instead,
+        // numbered and kept whatever its content
+        let too_long = whole_lines
+            && *buf_read_state == StartOfLine
+            && line.len() > options.max_buffered_line
+            && line.last() != Some(&options.record_separator);
+        if too_long {
+            warn_too_long(err, options, warned_too_long)?;
+        }
+        let whole_line = *buf_read_state == StartOfLine && !too_long;
+
+        // Let the caller rewrite the line's content, keeping its ending. Only
+        // a line cut short by the output limit comes in pieces, and then
+        // just the first is rewritten.
+        if let Some(transform) = hooks.transform.as_mut().filter(|_| whole_line) {
+            let content_len =
+                line.len() - usize::from(line.last() == Some(&options.record_separator));
+            let mut rewritten = transform(&line[..content_len]).into_owned();
+            rewritten.extend_from_slice(&line[content_len..]);
+            *line = rewritten;
+        }
+
+        // Drop the line if the caller's filter rejects its content
+        if let Some(filter) = hooks.filter.as_mut().filter(|_| whole_line) {
+            let separator = [options.record_separator];
+            if !filter(line.strip_suffix(&separator).unwrap_or(&line[..])) {
+                if options.number_filtered_lines {
+                    *line_count += 1;
+                    stats.next_line_number = *line_count;
+                }
+                return Ok(());
+            }
+        }
+
+        // Drop a line the same as the last one written. A line cut short by
+        // the end of an input might go on in the next one, so it isn't
+        // compared.
+        if options.unique_adjacent && *buf_read_state == StartOfLine {
+            if line.last() != Some(&options.record_separator) {
+                *previous_line = None;
+            } else if previous_line.as_ref() == Some(&*line) {
+                return Ok(());
+            } else {
+                *previous_line = Some(line.clone());
+            }
+        }
+
+        // Blank lines are dropped from the start of the output, and held
+        // back from its end until something follows them
+        let blank = *buf_read_state == StartOfLine
+            && line
+                .strip_suffix(&[options.record_separator])
+                .is_some_and(|rest| matches!(rest, b"" | b"\r"));
+        if blank && options.trim_leading_blanks && *at_start {
+            return Ok(());
+        }
+        if !blank {
+            out.write_all(blank_lines)?;
+            blank_lines.clear();
+        }
+        *at_start = false;
+        let sink: &mut dyn Write = if blank && options.trim_trailing_blanks {
+            &mut *blank_lines
+        } else {
+            &mut *out
+        };
+
+        // Structured output formats report where each line came from instead
+        // of decorating it
+        if *buf_read_state == StartOfLine && options.format != OutputFormat::Text {
+            held.origin = LineOrigin {
+                path: path.clone(),
+                number: *line_count,
+            };
+            *line_count += 1;
+        }
+
+        // Add the time the line is written at, if configured
+        if let Some(format) = &options.timestamp_format {
+            if *buf_read_state == StartOfLine && options.format == OutputFormat::Text {
+                let timestamp = format_timestamp(format, (options.clock)());
+                write!(sink, "{timestamp} ")?;
+            }
+        }
+
+        // Add where in which input the line starts, if configured
+        if *buf_read_state == StartOfLine
+            && options.source_byte_gutter
+            && options.format == OutputFormat::Text
+        {
+            let offset = stats.bytes - input.start_bytes - bytes_read as u64;
+            write!(sink, "[file {} @ {offset}] ", index + 1)?;
+        }
+
+        // Add the filename if configured, if we're at the start of a line
+        if *buf_read_state == StartOfLine
+            && options.filename_prefix
+            && options.format == OutputFormat::Text
+        {
+            let path = path.as_deref().unwrap_or("(standard input)");
+            write!(sink, "{path}:")?;
+        }
+
+        // Add the offset the line starts at among all the input read, in
+        // place of its number, if configured
+        if *buf_read_state == StartOfLine
+            && options.byte_offsets
+            && options.format == OutputFormat::Text
+        {
+            let offset = stats.bytes - bytes_read as u64;
+            write_line_number(sink, offset, path.as_deref(), options)?;
+        }
+
+        // Add line numbers if configured, if we're at the start of a line
+        if *buf_read_state == StartOfLine
+            && options.line_numbers
+            && !options.byte_offsets
+            && options.format == OutputFormat::Text
+            && options
+                .number_matching
+                .as_ref()
+                .filter(|_| !too_long)
+                .is_none_or(|pattern| {
+                    let separator = [options.record_separator];
+                    pattern.is_match(line.strip_suffix(&separator).unwrap_or(&line[..]))
+                })
+        {
+            if line_count.is_multiple_of(options.line_number_every.max(1)) {
+                let path = path.as_deref();
+                let (number, past_cap) = match options.line_number_cap {
+                    Some((max, CapMode::Wrap)) => {
+                        (line_count.saturating_sub(1) % max.max(1) + 1, false)
+                    }
+                    Some((max, CapMode::Stop)) => (*line_count, *line_count > max),
+                    None => (*line_count, false),
+                };
+                match &options.blank_line_marker {
+                    _ if past_cap => write_gutter(sink, "", path, options)?,
+                    Some(marker) if blank => write_gutter(sink, marker, path, options)?,
+                    _ => write_line_number(sink, number as u64, path, options)?,
+                }
+            }
+            *line_count += 1;
+        }
+        stats.next_line_number = *line_count;
+
+        if *buf_read_state == StartOfLine && options.format == OutputFormat::Text {
+            if let Some(line_prefix) = &options.line_prefix {
+                sink.write_all(line_prefix.as_bytes())?;
+            }
+        }
+
+        // Without a newline, we either hit EOF, or are in the middle of a
+        // super long line
+        let newline = line.last() == Some(&options.record_separator);
+        let mut content = &line[..line.len() - usize::from(newline)];
+        if let Some(last) = content.last() {
+            input.ends_with_cr = *last == b'\r';
+        }
+
+        // With line ends shown, a carriage return ending the line is shown
+        // along with them, so it's held back until we know whether it does
+        let mut pending = &b""[..];
+        if (options.line_ends || options.visible_line_endings || options.preserve_dominant_ending)
+            && options.format == OutputFormat::Text
+        {
+            if held.cr && !content.is_empty() {
+                pending = b"\r";
+                held.cr = false;
+            }
+            if let Some(rest) = content.strip_suffix(b"\r") {
+                content = rest;
+                held.cr = true;
+            }
+        }
+        for bytes in [pending, content] {
+            let bytes = match options.strip_ansi {
+                true => input.ansi.strip(bytes),
+                false => Cow::Borrowed(bytes),
+            };
+            let bytes = match (options.case_fold, bytes) {
+                (Some(fold), Cow::Borrowed(bytes)) => fold_case(bytes, fold, options.unicode_case),
+                (Some(fold), Cow::Owned(bytes)) => {
+                    Cow::Owned(fold_case(&bytes, fold, options.unicode_case).into_owned())
+                }
+                (None, bytes) => bytes,
+            };
+            let bytes = match (options.show_carriage_returns, bytes) {
+                (true, Cow::Borrowed(bytes)) => show_carriage_returns(bytes),
+                (true, Cow::Owned(bytes)) => Cow::Owned(show_carriage_returns(&bytes).into_owned()),
+                (false, bytes) => bytes,
+            };
+            if hold_lines && !held.too_long {
+                held.content.extend_from_slice(&bytes);
+            } else {
+                sink.write_all(&bytes)?;
+            }
+        }
+
+        // A line too long to hold is written as it comes instead, without
+        // the transforms that needed all of it
+        if hold_lines
+            && !held.too_long
+            && options.format == OutputFormat::Text
+            && held.content.len() > options.max_buffered_line
+        {
+            warn_too_long(err, options, warned_too_long)?;
+            sink.write_all(&held.content)?;
+            held.content.clear();
+            held.too_long = true;
+        }
+
+        if newline {
+            if input.ends_with_cr {
+                input.seen_crlf = true;
+            } else {
+                input.seen_lf = true;
+            }
+            input.ends_with_cr = false;
+            *buf_read_state = StartOfLine;
+            end_held_line(sink, held, options, true)?;
+        } else {
+            *buf_read_state = MiddleOfLine;
+        }
+
+        out.flush()?;
+
+        // A transform that rejects this input abandons the rest of it
+        if let Some(e) = out.take_error() {
+            if options.strict {
+                return Err(e);
+            }
+            let error = CatError::new(index, e);
+            report_error(error_out(out.raw(), err, options), options, stats, error)?;
+            out.flush()?;
+            held.content.clear();
+            held.cr = false;
+            held.too_long = false;
+            *buf_read_state = StartOfLine;
+            hooks.ended(&input.desc, input.stats(stats));
+            *current = None;
+        }
+        Ok(())
+    }
+
+    // Ends the output once all the inputs have been read
+    fn end<E: Write>(&mut self, err: &mut E) -> io::Result<()> {
+        let options = &self.options;
+        let error_prefix = &options.error_prefix;
+        let out = &mut self.out;
+        let stats = &mut self.stats;
+
+        if self.buf_read_state == BufReadState::MiddleOfLine {
+            end_held_line(out, &mut self.held, options, false)?;
+        }
+        #[cfg(feature = "serde_json")]
+        if options.format == OutputFormat::Json {
+            out.write_all(b"]")?;
+            out.write_all(options.line_ending.as_bytes())?;
+        }
+        if let Some(suffix) = &options.suffix {
+            out.write_all(suffix.as_bytes())?;
+        }
+        out.finish()?;
+        if let Some(e) = out.take_error() {
+            // Only the end of the last input can be left to reject
+            let error = CatError::new(self.last_input, e);
+            report_error(error_out(out.raw(), err, options), options, stats, error)?;
+            out.flush()?;
+        }
+        if options.summary {
+            writeln!(err, "{error_prefix}: {stats}")?;
+            err.flush()?;
+        }
+
+        // Empty output is left empty
+        let line_ending = options.line_ending.as_bytes();
+        let out = out.raw();
+        if options.ensure_trailing_newline
+            && out.last.is_some_and(|b| Some(&b) != line_ending.last())
+        {
+            out.write_all(line_ending)?;
+            out.flush()?;
+        }
+        out.inner.finish()?;
+        stats.ended_with_newline = out.last == Some(b'\n');
+        Ok(())
+    }
+}
+
+/// Concatenates `readers` into `out` the way `Concatenator` does its inputs,
+/// for callers with readers and writers of their own. The readers are
+/// unnamed, like standard input, and warnings go to standard error.
+///
+/// ```
+/// use cat::{concatenate_readers, CatOptions};
+/// use std::io::Cursor;
+///
+/// let readers = [Cursor::new("first\n"), Cursor::new("second\n")];
+/// let mut out = Vec::new();
+/// let options = CatOptions {
+///     line_numbers: true,
+///     ..Default::default()
+/// };
+/// let stats = concatenate_readers(readers, &mut out, &options).unwrap();
+///
+/// assert!(stats.errors.is_empty());
+/// assert_eq!(out, b"     1\tfirst\n     2\tsecond\n");
+/// ```
+pub fn concatenate_readers<R: BufRead, W: Write>(
+    readers: impl IntoIterator<Item = R>,
+    out: &mut W,
+    options: &CatOptions,
+) -> io::Result<CatStats> {
+    let ins = readers
+        .into_iter()
+        .map(|reader| Ok(Input::stdin(reader)))
+        .collect();
+    cat(ins, out, &mut io::stderr(), options)
+}
+
+/// Concatenates in-memory `inputs` like `concatenate_readers()` does its
+/// readers, returning the output.
+pub fn cat_bytes(inputs: &[&[u8]], options: &CatOptions) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    concatenate_readers(inputs.iter().map(io::Cursor::new), &mut out, options)?;
+    Ok(out)
+}
+
+// Reader yielding what `cat()` would write for the inner reader, catting
+// just enough of it to have something to read each time it runs dry.
+// Warnings go to standard error, unless `with_warnings()` sends them
+// elsewhere.
+pub struct CatReader<R, E = io::Stderr> {
+    records: Records<R, ReadAhead>,
+    err: E,
+    // How much of what's been catted was read
+    pos: usize,
+    done: bool,
+}
+
+impl<R: BufRead> CatReader<R> {
+    pub fn new(inner: R, options: CatOptions) -> io::Result<Self> {
+        let ins = vec![Ok(Input::stdin(inner))];
+        Ok(CatReader {
+            records: Records::new(ins, ReadAhead::default(), &options, InputHooks::default())?,
+            err: io::stderr(),
+            pos: 0,
+            done: false,
+        })
+    }
+}
+
+impl<R, E> CatReader<R, E> {
+    pub fn with_warnings<F: Write>(self, err: F) -> CatReader<R, F> {
+        CatReader {
+            records: self.records,
+            err,
+            pos: self.pos,
+            done: self.done,
+        }
+    }
+
+    // What was read, once everything has been
+    pub fn stats(&self) -> Option<&CatStats> {
+        self.done.then_some(&self.records.stats)
+    }
+}
+
+impl<R: BufRead, E: Write> io::Read for CatReader<R, E> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(out.len());
+        out[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead, E: Write> BufRead for CatReader<R, E> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.pos == self.records.output().buf.len() {
+            self.records.output().buf.clear();
+            self.pos = 0;
+            let mut sink = io::sink();
+            let mut err: &mut dyn Write = match self.records.options.quiet {
+                true => &mut sink,
+                false => &mut self.err,
+            };
+            match self.records.step(&mut err) {
+                Ok(true) => {}
+                Ok(false) => {
+                    let records = &mut self.records;
+                    records
+                        .out
+                        .out
+                        .inner
+                        .inner
+                        .inner
+                        .finish(&mut records.stats)?;
+                    self.done = true;
+                    break;
+                }
+                Err(e) => self.records.stop(e, &mut err)?,
+            }
+        }
+        Ok(&self.records.output().buf[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+}
+
+// What's been catted for a reader and not yet read. It's compressed and
+// checksummed first if that's asked for.
+#[derive(Default)]
+struct ReadAhead {
+    buf: Vec<u8>,
+    #[cfg(feature = "flate2")]
+    gzip: Option<GzipWriter<Vec<u8>>>,
+    #[cfg(feature = "checksum")]
+    checksum: Option<ChecksumWriter<io::Sink>>,
+}
+
+impl ReadAhead {
+    fn pass(&mut self, bytes: &[u8]) -> io::Result<()> {
+        #[cfg(feature = "checksum")]
+        if let Some(checksum) = &mut self.checksum {
+            checksum.write_all(bytes)?;
+        }
+        self.buf.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    // Adds whatever compression was holding back, and the checksum of
+    // everything to `stats`
+    #[cfg_attr(not(feature = "checksum"), allow(unused_variables))]
+    fn finish(&mut self, stats: &mut CatStats) -> io::Result<()> {
+        #[cfg(feature = "flate2")]
+        if let Some(gzip) = self.gzip.take() {
+            let rest = gzip.finish()?;
+            self.pass(&rest)?;
+        }
+        #[cfg(feature = "checksum")]
+        if let Some(checksum) = self.checksum.take() {
+            stats.checksum = Some(checksum.hex_digest());
+        }
+        Ok(())
+    }
+}
+
+impl Write for ReadAhead {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        #[cfg(feature = "flate2")]
+        if let Some(gzip) = &mut self.gzip {
+            gzip.write_all(buf)?;
+            let compressed = std::mem::take(gzip.0.get_mut());
+            self.pass(&compressed)?;
+            return Ok(buf.len());
+        }
+        self.pass(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Reader yielding what a `Concatenator` would write, catting it as it's
+// read like `CatReader`. The first input that failed is returned as an
+// error once everything else has been read.
+pub struct ConcatenatorReader {
+    source: ReaderSource,
+    // Where to save the next line number once the catting is done
+    number_state_file: Option<PathBuf>,
+    failure_returned: bool,
+}
+
+// Listing and counting don't cat anything, so they're done up front
+enum ReaderSource {
+    Catting(Box<CatReader<Box<dyn BufRead>, Box<dyn Write>>>),
+    Listed(io::Cursor<Vec<u8>>, CatStats),
+}
+
+impl ConcatenatorReader {
+    pub fn new(catter: Concatenator) -> io::Result<Self> {
+        let mut catter = catter;
+        catter.options.syntax_highlight = false;
+        let mut err = catter.warnings();
+        let ins = catter.open_inputs(&mut err)?;
+        let mut ahead = catter.read_ahead();
+        let options = &catter.options;
+
+        if !options.dry_run && options.grep_count.is_none() {
+            let reader = CatReader {
+                records: Records::new(ins, ahead, options, catter.hooks)?,
+                err,
+                pos: 0,
+                done: false,
+            };
+            return Ok(ConcatenatorReader {
+                source: ReaderSource::Catting(Box::new(reader)),
+                number_state_file: catter.number_state_file,
+                failure_returned: false,
+            });
+        }
+        let mut stats = match &options.grep_count {
+            Some(pattern) if !options.dry_run => count_matches(ins, &mut ahead, pattern, options)?,
+            _ => list_inputs(ins, &mut ahead, options)?,
+        };
+        ahead.finish(&mut stats)?;
+        Ok(ConcatenatorReader {
+            source: ReaderSource::Listed(io::Cursor::new(ahead.buf), stats),
+            number_state_file: None,
+            failure_returned: false,
+        })
+    }
+
+    // What was read, once everything has been
+    pub fn stats(&self) -> Option<&CatStats> {
+        match &self.source {
+            ReaderSource::Catting(reader) => reader.stats(),
+            ReaderSource::Listed(_, stats) => Some(stats),
+        }
+    }
+
+    fn source(&mut self) -> &mut dyn BufRead {
+        match &mut self.source {
+            ReaderSource::Catting(reader) => reader,
+            ReaderSource::Listed(out, _) => out,
+        }
+    }
+}
+
+impl io::Read for ConcatenatorReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(out.len());
+        out[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for ConcatenatorReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.source().fill_buf()?.is_empty() {
+            if let Some(path) = self.number_state_file.take() {
+                if let Some(stats) = self.stats() {
+                    write_number_state(&path, stats)?;
+                }
+            }
+            if !self.failure_returned {
+                self.failure_returned = true;
+                let failed = self.stats().and_then(|stats| stats.errors.first());
+                if let Some(error) = failed {
+                    return Err(io::Error::other(error.to_string()));
+                }
+            }
+        }
+        self.source().fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.source().consume(amt);
+    }
+}
+
+// Lists the inputs instead of catting them: the name of each one that could
+// be opened, and the error of each one that couldn't
+fn list_inputs<R, W: Write>(
+    ins: Vec<Result<Input<R>, Box<dyn Error>>>,
+    out: &mut W,
+    options: &CatOptions,
+) -> io::Result<CatStats> {
+    let mut stats = CatStats::default();
+    ignore_broken_pipe(list_into(ins, out, options, &mut stats))?;
+    Ok(stats)
+}
+
+// Does the listing for `list_inputs()`, counting into `stats` as it goes
+fn list_into<R, W: Write>(
+    ins: Vec<Result<Input<R>, Box<dyn Error>>>,
+    out: &mut W,
+    options: &CatOptions,
+    stats: &mut CatStats,
+) -> io::Result<()> {
+    for (index, input) in ins.into_iter().enumerate() {
+        match input {
+            Ok(Input { path, .. }) => {
+                writeln!(out, "{}", path.as_deref().unwrap_or(STDIN_NAME))?;
+                stats.files += 1;
+            }
+            Err(e) => {
+                let error = CatError::new(index, e);
+                report_error(out, options, stats, error)?;
+            }
+        }
+    }
+    out.flush()
+}
+
+// Counts the lines of each input matching `pattern` instead of catting
+// them, writing the count under the input's name. Inputs that can't be
+// read are reported like `cat()` does.
+fn count_matches<R: BufRead, W: Write>(
+    ins: Vec<Result<Input<R>, Box<dyn Error>>>,
+    out: &mut W,
+    pattern: &LinePattern,
+    options: &CatOptions,
+) -> io::Result<CatStats> {
+    let mut stats = CatStats::default();
+    ignore_broken_pipe(count_into(ins, out, pattern, options, &mut stats))?;
+    Ok(stats)
+}
+
+// Does the counting for `count_matches()`, counting into `stats` as it goes
+fn count_into<R: BufRead, W: Write>(
+    ins: Vec<Result<Input<R>, Box<dyn Error>>>,
+    out: &mut W,
+    pattern: &LinePattern,
+    options: &CatOptions,
+    stats: &mut CatStats,
+) -> io::Result<()> {
+    let mut line = Vec::new();
+    'outer: for (index, input) in ins.into_iter().enumerate() {
+        let Input { path, reader } = match input {
+            Ok(input) => input,
+            Err(e) => {
+                let error = CatError::new(index, e);
+                report_error(out, options, stats, error)?;
+                continue;
+            }
+        };
+        let mut input = decode_input(reader, options);
+        stats.files += 1;
+
+        let mut count = 0;
+        loop {
+            line.clear();
+            let bytes_read = match input.read_until(options.record_separator, &mut line) {
+                Ok(0) => break,
+                Ok(bytes_read) => bytes_read,
+                Err(e) => {
+                    let error = CatError::new(index, e);
+                    report_error(out, options, stats, error)?;
+                    continue 'outer;
+                }
+            };
+            stats.bytes += bytes_read as u64;
+            stats.lines += 1;
+            let record = line.strip_suffix(&[options.record_separator]);
+            if pattern.is_match(record.unwrap_or(&line)) {
+                count += 1;
+            }
+        }
+        writeln!(out, "{}: {count}", path.as_deref().unwrap_or(STDIN_NAME))?;
+    }
+    out.flush()
+}
+
+fn format_line_number(number: u64, options: &CatOptions) -> String {
+    match (options.number_format, options.number_prefix) {
+        (NumberFormat::Dec, _) => number.to_string(),
+        (NumberFormat::Hex, false) => format!("{number:x}"),
+        (NumberFormat::Hex, true) => format!("{number:#x}"),
+        (NumberFormat::Oct, false) => format!("{number:o}"),
+        (NumberFormat::Oct, true) => format!("{number:#o}"),
+    }
+}
+
+// Formats `time` as UTC, going by the strftime-like `format`. Unknown
+// conversions are written as they are.
+fn format_timestamp(format: &str, time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+    let (hour, minute, second) = (secs_of_day / 3600, secs_of_day / 60 % 60, secs_of_day % 60);
+
+    // Civil date from days since 1970-01-01, in eras of 400 years that
+    // start on March 1st
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    let mut formatted = String::with_capacity(format.len());
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            formatted.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => formatted.push_str(&year.to_string()),
+            Some('m') => formatted.push_str(&format!("{month:02}")),
+            Some('d') => formatted.push_str(&format!("{day:02}")),
+            Some('H') => formatted.push_str(&format!("{hour:02}")),
+            Some('M') => formatted.push_str(&format!("{minute:02}")),
+            Some('S') => formatted.push_str(&format!("{second:02}")),
+            Some('s') => formatted.push_str(&secs.to_string()),
+            Some('%') => formatted.push('%'),
+            Some(other) => {
+                formatted.push('%');
+                formatted.push(other);
+            }
+            None => formatted.push('%'),
+        }
+    }
+    formatted
+}
+
+// Writes the number in the gutter before a line's content, in the
+// configured style
+fn write_line_number<W: Write + ?Sized>(
+    out: &mut W,
+    number: u64,
+    path: Option<&str>,
+    options: &CatOptions,
+) -> io::Result<()> {
+    write_gutter(out, &format_line_number(number, options), path, options)
+}
+
+// Writes `number`, or whatever stands in for it, in the gutter before a
+// line's content
+fn write_gutter<W: Write + ?Sized>(
+    out: &mut W,
+    number: &str,
+    path: Option<&str>,
+    options: &CatOptions,
+) -> io::Result<()> {
+    let width = options.line_number_width;
+    match &options.number_style {
+        NumberStyle::GnuRight => {
+            let number = format!("{number:>width$}");
+            let indent = number_indent(path, &number, options);
+            write!(out, "{number}{indent}")
+        }
+        NumberStyle::LeftColon => write!(out, "{number}: "),
+        NumberStyle::Custom {
+            separator,
+            left_align: true,
+        } => write!(out, "{number:<width$}{separator}"),
+        NumberStyle::Custom {
+            separator,
+            left_align: false,
+        } => write!(out, "{number:>width$}{separator}"),
+    }
+}
+
+// What separates a line number from the content. Numbering by display
+// width pads with spaces up to the next tab stop instead, counting the
+// columns that what comes before takes up in a terminal, where e.g. CJK
+// characters take up two each. Replacing the tab pads the same way, with
+// a column per character.
+fn number_indent(path: Option<&str>, number: &str, options: &CatOptions) -> Cow<'static, str> {
+    let pad = |column: usize| {
+        let tabstop = options.tabstop.unwrap_or(DEFAULT_TABSTOP);
+        " ".repeat(tabstop - column % tabstop).into()
+    };
+    let path = path.unwrap_or("(standard input)");
+
+    #[cfg(feature = "unicode-width")]
+    if options.display_width_numbering {
+        use unicode_width::UnicodeWidthStr;
+
+        let mut column = number.width();
+        if options.filename_prefix {
+            column += path.width() + 1;
+        }
+        return pad(column);
+    }
+    if options.replace_tabs_in_numbering {
+        let mut column = number.chars().count();
+        if options.filename_prefix {
+            column += path.chars().count() + 1;
+        }
+        return pad(column);
+    }
+    POST_LINE_NUM_INDENT.into()
+}
+
+// Drops ANSI escape sequences from content fed to it a piece at a time,
+// keeping track of one cut off at the end of a piece
+#[derive(Default)]
+struct AnsiStripper {
+    state: AnsiState,
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+enum AnsiState {
+    #[default]
+    Text,
+    Escape,
+    // A control sequence, `ESC [` then parameters up to a final byte
+    Csi,
+    // An operating system command, `ESC ]` up to BEL or `ESC \`
+    Osc,
+    OscEscape,
+}
+
+impl AnsiStripper {
+    fn strip<'a>(&mut self, bytes: &'a [u8]) -> Cow<'a, [u8]> {
+        use AnsiState::*;
+
+        if self.state == Text && !bytes.contains(&0x1b) {
+            return Cow::Borrowed(bytes);
+        }
+        let mut kept = Vec::with_capacity(bytes.len());
+        for &b in bytes {
+            self.state = match (self.state, b) {
+                (Text, 0x1b) => Escape,
+                (Text, _) => {
+                    kept.push(b);
+                    Text
+                }
+                (Escape, b'[') => Csi,
+                (Escape, b']') => Osc,
+                // Other escapes are just the one byte after it
+                (Escape, _) => Text,
+                (Csi, 0x20..=0x3f) => Csi,
+                (Csi, _) => Text,
+                (Osc, 0x07) => Text,
+                (Osc, 0x1b) => OscEscape,
+                (Osc, _) => Osc,
+                (OscEscape, _) => Text,
+            };
+        }
+        Cow::Owned(kept)
+    }
+}
+
+// `bytes` with each carriage return in it written as `^M`
+fn show_carriage_returns(bytes: &[u8]) -> Cow<'_, [u8]> {
+    if !bytes.contains(&b'\r') {
+        return Cow::Borrowed(bytes);
+    }
+    let mut shown = Vec::with_capacity(bytes.len() + 8);
+    for &b in bytes {
+        match b {
+            b'\r' => shown.extend_from_slice(b"^M"),
+            _ => shown.push(b),
+        }
+    }
+    Cow::Owned(shown)
+}
+
+// `bytes` with its letters folded to one case. With Unicode case, a
+// character split across two chunks of a very long line is left as it is.
+fn fold_case(bytes: &[u8], fold: CaseFold, unicode: bool) -> Cow<'_, [u8]> {
+    if !unicode {
+        return match fold {
+            CaseFold::Lower => Cow::Owned(bytes.to_ascii_lowercase()),
+            CaseFold::Upper => Cow::Owned(bytes.to_ascii_uppercase()),
+        };
+    }
+    let mut folded = Vec::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        let valid = match fold {
+            CaseFold::Lower => chunk.valid().to_lowercase(),
+            CaseFold::Upper => chunk.valid().to_uppercase(),
+        };
+        folded.extend_from_slice(valid.as_bytes());
+        folded.extend_from_slice(chunk.invalid());
+    }
+    Cow::Owned(folded)
+}
+
+// Warns about a line too long to hold whole, the first time there's one
+fn warn_too_long<E: Write>(err: &mut E, options: &CatOptions, warned: &mut bool) -> io::Result<()> {
+    if std::mem::replace(warned, true) {
+        return Ok(());
+    }
+    let max = options.max_buffered_line;
+    writeln!(
+        err,
+        "{}: line longer than {max} bytes, written untransformed",
+        options.error_prefix
+    )?;
+    err.flush()
+}
+
+// Writes `error` in place of content and records it in the stats. It's
+// recorded even if writing fails, so that it isn't lost if the output turns
+// out to be closed.
+fn report_error<W: Write + ?Sized>(
+    out: &mut W,
+    options: &CatOptions,
+    stats: &mut CatStats,
+    error: CatError,
+) -> io::Result<()> {
+    let written = write_error(out, options, &error);
+    stats.errors.push(error);
+    written
+}
+
+// Where errors met while catting are written: in place of content, unless
+// that's JSON records they'd break up, when they go with the warnings
+fn error_out<'a>(
+    out: &'a mut dyn Write,
+    err: &'a mut dyn Write,
+    options: &CatOptions,
+) -> &'a mut dyn Write {
+    if options.format == OutputFormat::Text {
+        out
+    } else {
+        err
+    }
+}
+
+// Writes an error in place of content, as a line of its own
+fn write_error<W: Write + ?Sized>(
+    out: &mut W,
+    options: &CatOptions,
+    e: impl Display,
+) -> io::Result<()> {
+    if options.quiet {
+        return Ok(());
+    }
+    write!(out, "{}: {e}", options.error_prefix)?;
+    out.write_all(options.line_ending.as_bytes())
+}
+
+// Where the line being written started
+#[derive(Default)]
+#[cfg_attr(not(feature = "serde_json"), allow(dead_code))]
+struct LineOrigin {
+    path: Option<String>,
+    number: usize,
+}
+
+// The line being written, when its content is held back until the whole
+// line has been seen
+#[derive(Default)]
+struct HeldLine {
+    content: Vec<u8>,
+    // Whether a carriage return was held back from the end of the content
+    cr: bool,
+    // Whether the line grew too long to hold, and is being written as read
+    too_long: bool,
+    // Lines written as records of a JSON array so far
+    #[cfg(feature = "serde_json")]
+    records: usize,
+    origin: LineOrigin,
+    // Highlights the lines of the current input, if it's being highlighted
+    #[cfg(feature = "syntect")]
+    highlighter: Option<syntect::easy::HighlightLines<'static>>,
+}
+
+// Finishes off the current line, writing out its content if it was held
+// back. `newline` tells whether the line was terminated by a newline in the
+// input, rather than being cut short by an error or the end of the input.
+fn end_held_line<W: Write + ?Sized>(
+    out: &mut W,
+    held: &mut HeldLine,
+    options: &CatOptions,
+    newline: bool,
+) -> io::Result<()> {
+    // A carriage return cut short of a newline is just content
+    if held.cr && !newline {
+        let cr: &[u8] = if options.show_carriage_returns {
+            b"^M"
+        } else {
+            b"\r"
+        };
+        held.content.extend_from_slice(cr);
+        held.cr = false;
+    }
+
+    let line = &mut held.content;
+    let too_long = std::mem::take(&mut held.too_long);
+    if options.trim_trailing_whitespace && !too_long {
+        trim_trailing_whitespace(line);
+    }
+    if options.reverse_lines && !too_long {
+        reverse_line(line);
+    }
+    if let Some((cols, mode)) = options.max_line_width.filter(|_| !too_long) {
+        limit_line_width(line, cols, mode, options.line_ending);
+    }
+
+    match options.format {
+        OutputFormat::Text => {
+            #[cfg(feature = "syntect")]
+            let highlighted = write_highlighted(out, held)?;
+            #[cfg(not(feature = "syntect"))]
+            let highlighted = false;
+            if !highlighted {
+                out.write_all(&held.content)?;
+            }
+            held.content.clear();
+            if let Some(line_suffix) = &options.line_suffix {
+                out.write_all(line_suffix.as_bytes())?;
+            }
+
+            if newline {
+                // Write line endings if configured
+                if options.visible_line_endings {
+                    let glyphs = if held.cr { "␍␊" } else { "␊" };
+                    out.write_all(glyphs.as_bytes())?;
+                    held.cr = false;
+                } else if options.line_ends && held.cr {
+                    write!(out, "^M$")?;
+                    held.cr = false;
+                } else if options.line_ends {
+                    write!(out, "$")?;
+                }
+                held.cr = false;
+                match &options.newline_replacement {
+                    Some(separator) => out.write_all(separator)?,
+                    None if options.record_separator != b'\n' => {
+                        out.write_all(&[options.record_separator])?
+                    }
+                    None => out.write_all(options.line_ending.as_bytes())?,
+                }
+            }
+            Ok(())
+        }
+        #[cfg(feature = "serde_json")]
+        OutputFormat::Ndjson | OutputFormat::Json => {
+            let record = serde_json::json!({
+                "file": held.origin.path,
+                "line": held.origin.number,
+                "text": String::from_utf8_lossy(&held.content),
+            });
+            held.content.clear();
+            if options.format == OutputFormat::Ndjson {
+                write!(out, "{record}")?;
+                return out.write_all(options.line_ending.as_bytes());
+            }
+            if held.records > 0 {
+                out.write_all(b",")?;
+            }
+            held.records += 1;
+            write!(out, "{record}")
+        }
+    }
+}
+
+// Syntax definitions and the theme used for highlighting, loaded on first use
+#[cfg(feature = "syntect")]
+fn highlighting() -> &'static (syntect::parsing::SyntaxSet, syntect::highlighting::Theme) {
+    use std::sync::OnceLock;
+    use syntect::{highlighting::ThemeSet, parsing::SyntaxSet};
+
+    static HIGHLIGHTING: OnceLock<(SyntaxSet, syntect::highlighting::Theme)> = OnceLock::new();
+    HIGHLIGHTING.get_or_init(|| {
+        let mut themes = ThemeSet::load_defaults();
+        (
+            SyntaxSet::load_defaults_nonewlines(),
+            themes.themes.remove("base16-ocean.dark").unwrap(),
+        )
+    })
+}
+
+// Highlighter for the file at `path`, going by its extension. Plain text
+// isn't worth highlighting, so it counts as unrecognized.
+#[cfg(feature = "syntect")]
+fn highlighter_for(path: &str) -> Option<syntect::easy::HighlightLines<'static>> {
+    let (syntaxes, theme) = highlighting();
+    let extension = std::path::Path::new(path).extension()?.to_str()?;
+    let syntax = syntaxes
+        .find_syntax_by_extension(extension)
+        .filter(|syntax| syntax.name != "Plain Text")?;
+    Some(syntect::easy::HighlightLines::new(syntax, theme))
+}
+
+// Writes the held line with terminal colors, if it's being highlighted and
+// can be. Returns whether it was written.
+#[cfg(feature = "syntect")]
+fn write_highlighted<W: Write + ?Sized>(out: &mut W, held: &mut HeldLine) -> io::Result<bool> {
+    let Some(highlighter) = &mut held.highlighter else {
+        return Ok(false);
+    };
+    let Ok(text) = std::str::from_utf8(&held.content) else {
+        return Ok(false);
+    };
+    let Ok(ranges) = highlighter.highlight_line(text, &highlighting().0) else {
+        return Ok(false);
+    };
+    let escaped = syntect::util::as_24_bit_terminal_escaped(&ranges, false);
+    write!(out, "{escaped}\x1b[0m")?;
+    Ok(true)
+}
+
+// Splits a line into its content and the carriage return of a CRLF line
+// ending, if it has one
+fn split_cr(line: &[u8]) -> (&[u8], &[u8]) {
+    match line.strip_suffix(b"\r") {
+        Some(content) => (content, b"\r"),
+        None => (line, b""),
+    }
+}
+
+// Removes spaces and tabs from the end of the line, keeping a trailing
+// carriage return in place
+fn trim_trailing_whitespace(line: &mut Vec<u8>) {
+    let (content, cr) = split_cr(line);
+    let trimmed_len = content
+        .iter()
+        .rposition(|b| *b != b' ' && *b != b'\t')
+        .map_or(0, |i| i + 1);
+    let cr_len = cr.len();
+    line.drain(trimmed_len..line.len() - cr_len);
+}
+
+// Truncates or wraps the line so that no more than `cols` characters, counted
+// in UTF-8 scalars, appear on a row
+fn limit_line_width(line: &mut Vec<u8>, cols: usize, mode: WrapMode, ending: LineEnding) {
+    let (content, cr) = split_cr(line);
+
+    // Byte offset of every character in the line, and of its end
+    let mut boundaries = content
+        .iter()
+        .enumerate()
+        .filter(|(_, b)| **b & 0xC0 != 0x80)
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    boundaries.push(content.len());
+    let chars = boundaries.len() - 1;
+    if chars <= cols {
+        return;
+    }
+
+    let mut limited = Vec::with_capacity(line.len());
+    match mode {
+        WrapMode::Truncate { ellipsis: true } if cols > 0 => {
+            limited.extend_from_slice(&content[..boundaries[cols - 1]]);
+            limited.extend_from_slice("\u{2026}".as_bytes());
+        }
+        // Not even the ellipsis fits in no columns at all
+        WrapMode::Truncate { .. } => {
+            limited.extend_from_slice(&content[..boundaries[cols]]);
+        }
+        WrapMode::Wrap => {
+            for start in (0..chars).step_by(cols.max(1)) {
+                if start > 0 {
+                    limited.extend_from_slice(ending.as_bytes());
+                }
+                let end = (start + cols.max(1)).min(chars);
+                limited.extend_from_slice(&content[boundaries[start]..boundaries[end]]);
+            }
+        }
+        WrapMode::Word => {
+            let cols = cols.max(1);
+            let is_blank = |i: usize| matches!(content[boundaries[i]], b' ' | b'\t');
+            let mut start = 0;
+            while chars - start > cols {
+                // Words longer than a row are still broken at the limit
+                let end = (start..start + cols)
+                    .rev()
+                    .find(|i| is_blank(*i))
+                    .map_or(start + cols, |i| i + 1);
+                limited.extend_from_slice(&content[boundaries[start]..boundaries[end]]);
+                limited.extend_from_slice(ending.as_bytes());
+                start = end;
+            }
+            limited.extend_from_slice(&content[boundaries[start]..]);
+        }
+    }
+    limited.extend_from_slice(cr);
+    *line = limited;
+}
+
+// Reverses the order of the characters in the line. A trailing carriage
+// return belongs to the line ending and stays put, and bytes that aren't
+// valid UTF-8 are reversed individually.
+fn reverse_line(line: &mut Vec<u8>) {
+    let (content, cr) = split_cr(line);
+
+    let mut units = Vec::new();
+    for chunk in content.utf8_chunks() {
+        let valid = chunk.valid();
+        units.extend(
+            valid
+                .char_indices()
+                .map(|(i, c)| &valid.as_bytes()[i..i + c.len_utf8()]),
+        );
+        units.extend(chunk.invalid().chunks(1));
+    }
+
+    let mut reversed = Vec::with_capacity(line.len());
+    for unit in units.into_iter().rev() {
+        reversed.extend_from_slice(unit);
+    }
+    reversed.extend_from_slice(cr);
+    *line = reversed;
+}
+
+#[cfg(test)]
+mod test_dir;
+
+#[cfg(test)]
+#[allow(clippy::useless_vec)]
+mod cat_tests {
+    use std::{
+        error::Error,
+        io::{self, Cursor},
+        str,
+    };
+
+    use crate::{test_dir::TempDir, CatOptions, Input, POST_LINE_NUM_INDENT};
+
+    use super::{cat, drop_reused_stdin, list_inputs};
+
+    const INPUT_STREAM_1: &str = "This is the first file...
+Second line of first file now
+Not ending with a new line";
+    const INPUT_STREAM_2: &str = "This is the second file...
+Second line of second file now
+Going to end with a new line
+";
+    const INPUT_STREAM_3: &str = "This is the third file...
+Second line of third file now
+Not ending with a new line";
+
+    const ERROR_1: &str = "Oops, something went wrong!";
+
+    // Single digit line numbers, right-justified in the default width
+    const PRE_LINE_NUM_INDENT: &str = "     ";
+
+    fn named<T>(path: &str, content: T) -> Result<Input<Cursor<T>>, Box<dyn Error>> {
+        Ok(Input::new(path, Cursor::new(content)))
+    }
+
+    #[cfg(feature = "arboard")]
+    struct MockClipboard(Result<&'static str, &'static str>);
+
+    #[cfg(feature = "arboard")]
+    impl crate::ClipboardProvider for MockClipboard {
+        fn get_text(&mut self) -> Result<String, String> {
+            self.0.map(str::to_string).map_err(str::to_string)
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "arboard")]
+    fn clipboard_input() {
+        use crate::{Concatenator, InputSource};
+
+        let mut out = Vec::<u8>::default();
+        let stats = Concatenator::new(vec![
+            InputSource::Clipboard(Box::new(MockClipboard(Ok("copied\ntext")))),
+            InputSource::Clipboard(Box::new(MockClipboard(Err("no text on the clipboard")))),
+        ])
+        .with_line_numbers()
+        .with_quiet()
+        .concatenate_into(&mut out)
+        .unwrap();
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "     1\tcopied\n     2\ttext"
+        );
+        assert_eq!(stats.errors.len(), 1);
+        assert_eq!(
+            stats.errors[0].to_string(),
+            "clipboard: no text on the clipboard"
+        );
+    }
+
+    #[test]
+    fn concatenate_into_borrowed_writer() -> io::Result<()> {
+        use crate::Concatenator;
+        use std::io::Write;
+
+        let mut out = b"before\n".to_vec();
+        let stats = Concatenator::default()
+            .add_reader(Cursor::new(INPUT_STREAM_2))
+            .with_line_numbers()
+            .concatenate_into(&mut out)?;
+        out.write_all(b"after\n")?;
+
+        let numbered = INPUT_STREAM_2
+            .lines()
+            .enumerate()
+            .map(|(i, line)| format!("{:>6}\t{line}\n", i + 1))
+            .collect::<String>();
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            format!("before\n{numbered}after\n")
+        );
+        assert_eq!(stats.lines, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn bytes_inputs() -> io::Result<()> {
+        use crate::{Concatenator, InputSource};
+
+        let mut out = Vec::<u8>::default();
+        let stats = Concatenator::new(vec![
+            InputSource::Bytes(b"first\nsecond\n".to_vec()),
+            InputSource::Bytes(b"third\n".to_vec()),
+        ])
+        .with_line_numbers()
+        .concatenate_into(&mut out)?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "     1\tfirst\n     2\tsecond\n     3\tthird\n"
+        );
+        assert_eq!(stats.files, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn line_transform_rewrites_content() -> io::Result<()> {
+        use crate::Concatenator;
+        use std::borrow::Cow;
+
+        let catter = || {
+            Concatenator::default()
+                .add_reader(Cursor::new("first line\nsecond\n"))
+                .add_reader(Cursor::new("third\r\nlast"))
+                .with_line_numbers()
+        };
+
+        let mut out = Vec::<u8>::default();
+        catter()
+            .with_line_transform(|line| Cow::Owned(line.to_ascii_uppercase()))
+            .concatenate_into(&mut out)?;
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "     1\tFIRST LINE\n     2\tSECOND\n     3\tTHIRD\r\n     4\tLAST"
+        );
+
+        // Lines left empty still end
+        let mut out = Vec::<u8>::default();
+        catter()
+            .with_line_transform(|line| match line.starts_with(b"s") {
+                true => Cow::Borrowed(&[]),
+                false => Cow::Borrowed(line),
+            })
+            .concatenate_into(&mut out)?;
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "     1\tfirst line\n     2\t\n     3\tthird\r\n     4\tlast"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn line_filter_drops_lines() -> io::Result<()> {
+        use crate::Concatenator;
+
+        let catter = || {
+            Concatenator::default()
+                .add_reader(Cursor::new("keep\n# drop\nkeep #\n"))
+                .add_reader(Cursor::new("also kept\n#last"))
+                .add_reader(Cursor::new("after\n"))
+                .with_line_numbers()
+                .with_line_filter(|line| !line.contains(&b'#'))
+        };
+
+        let mut out = Vec::<u8>::default();
+        let stats = catter().concatenate_into(&mut out)?;
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "     1\tkeep\n     2\talso kept\n     3\tafter\n"
+        );
+        assert_eq!(stats.lines, 6);
+
+        let mut out = Vec::<u8>::default();
+        catter()
+            .with_number_filtered_lines()
+            .concatenate_into(&mut out)?;
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "     1\tkeep\n     4\talso kept\n     6\tafter\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn line_transform_drops_lines_through_filter() -> io::Result<()> {
+        use crate::Concatenator;
+        use std::borrow::Cow;
+
+        const DROP: &[u8] = b"\0drop";
+        let mut out = Vec::<u8>::default();
+        Concatenator::default()
+            .add_reader(Cursor::new("one\nskip two\nthree"))
+            .with_line_transform(|line| match line.starts_with(b"skip") {
+                true => Cow::Borrowed(DROP),
+                false => Cow::Owned(line.to_ascii_uppercase()),
+            })
+            .with_line_filter(|line| line != DROP)
+            .concatenate_into(&mut out)?;
+
+        assert_eq!(str::from_utf8(&out).unwrap(), "ONE\nTHREE");
+        Ok(())
+    }
+
+    #[test]
+    fn no_inputs_no_stdin() -> io::Result<()> {
+        use crate::Concatenator;
+
+        let mut out = Vec::<u8>::default();
+        let stats = Concatenator::default().concatenate_into(&mut out)?;
+
+        assert_eq!(out.len(), 0);
+        assert_eq!(stats.files, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn no_ins_no_out() -> io::Result<()> {
+        let ins = vec![named("input", String::new())];
+        let mut out = Vec::<u8>::default();
+        cat(ins, &mut out, &mut io::sink(), &CatOptions::default())?;
+
+        assert_eq!(out.len(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn one_in_correct_out() -> io::Result<()> {
+        let ins = vec![named("first.txt", INPUT_STREAM_1)];
+        let mut out = Vec::<u8>::default();
+        cat(ins, &mut out, &mut io::sink(), &CatOptions::default())?;
+
+        assert_eq!(str::from_utf8(&out).unwrap(), INPUT_STREAM_1);
+        Ok(())
+    }
+
+    #[test]
+    fn one_in_error_correct_out() -> io::Result<()> {
+        let ins: Vec<Result<Input<Cursor<Vec<u8>>>, _>> = vec![Err(ERROR_1.into())];
+        let mut out = Vec::<u8>::default();
+        cat(ins, &mut out, &mut io::sink(), &CatOptions::default())?;
+
+        assert_eq!(str::from_utf8(&out).unwrap(), format!("cat: {ERROR_1}\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn multiple_ins_correct_out() -> io::Result<()> {
+        let ins = vec![
+            named("first.txt", INPUT_STREAM_1),
+            named("second.txt", INPUT_STREAM_2),
+            named("third.txt", INPUT_STREAM_3),
+        ];
+        let mut out = Vec::<u8>::default();
+        cat(ins, &mut out, &mut io::sink(), &CatOptions::default())?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            format!("{INPUT_STREAM_1}{INPUT_STREAM_2}{INPUT_STREAM_3}")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn multiple_ins_with_error_correct_out() -> io::Result<()> {
+        let ins = vec![
+            named("first.txt", INPUT_STREAM_1),
+            Err(ERROR_1.into()),
+            named("second.txt", INPUT_STREAM_2),
+            named("third.txt", INPUT_STREAM_3),
+        ];
+        let mut out = Vec::<u8>::default();
+        cat(ins, &mut out, &mut io::sink(), &CatOptions::default())?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            format!("{INPUT_STREAM_1}cat: {ERROR_1}\n{INPUT_STREAM_2}{INPUT_STREAM_3}")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn line_nums_correct_out() -> io::Result<()> {
+        let ins = vec![
+            named("first.txt", INPUT_STREAM_1),
+            named("second.txt", INPUT_STREAM_2),
+            named("third.txt", INPUT_STREAM_3),
+        ];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                ..Default::default()
+            },
+        )?;
+
+        let (lines_1, lines_2, lines_3) = (
+            INPUT_STREAM_1.lines().collect::<Vec<_>>(),
+            INPUT_STREAM_2.lines().collect::<Vec<_>>(),
+            INPUT_STREAM_3.lines().collect::<Vec<_>>(),
+        );
+
+        let expected_out = vec![
+            format!(
+                "{PRE_LINE_NUM_INDENT}1{POST_LINE_NUM_INDENT}{}\n",
+                lines_1[0]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}2{POST_LINE_NUM_INDENT}{}\n",
+                lines_1[1]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}3{POST_LINE_NUM_INDENT}{}{}\n",
+                lines_1[2], lines_2[0]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}4{POST_LINE_NUM_INDENT}{}\n",
+                lines_2[1]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}5{POST_LINE_NUM_INDENT}{}\n",
+                lines_2[2]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}6{POST_LINE_NUM_INDENT}{}\n",
+                lines_3[0]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}7{POST_LINE_NUM_INDENT}{}\n",
+                lines_3[1]
+            ),
+            format!("{PRE_LINE_NUM_INDENT}8{POST_LINE_NUM_INDENT}{}", lines_3[2]),
+        ];
+
+        assert_eq!(str::from_utf8(&out).unwrap(), expected_out.join(""));
+        Ok(())
+    }
+
+    #[test]
+    fn line_nums_with_error_correct_out() -> io::Result<()> {
+        let ins = vec![
+            named("first.txt", INPUT_STREAM_1),
+            Err(ERROR_1.into()),
+            named("second.txt", INPUT_STREAM_2),
+            named("third.txt", INPUT_STREAM_3),
+        ];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                ..Default::default()
+            },
+        )?;
+
+        let (lines_1, lines_2, lines_3) = (
+            INPUT_STREAM_1.lines().collect::<Vec<_>>(),
+            INPUT_STREAM_2.lines().collect::<Vec<_>>(),
+            INPUT_STREAM_3.lines().collect::<Vec<_>>(),
+        );
+
+        let expected_out = vec![
+            format!(
+                "{PRE_LINE_NUM_INDENT}1{POST_LINE_NUM_INDENT}{}\n",
+                lines_1[0]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}2{POST_LINE_NUM_INDENT}{}\n",
+                lines_1[1]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}3{POST_LINE_NUM_INDENT}{}cat: {ERROR_1}\n",
+                lines_1[2]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}4{POST_LINE_NUM_INDENT}{}\n",
+                lines_2[0]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}5{POST_LINE_NUM_INDENT}{}\n",
+                lines_2[1]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}6{POST_LINE_NUM_INDENT}{}\n",
+                lines_2[2]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}7{POST_LINE_NUM_INDENT}{}\n",
+                lines_3[0]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}8{POST_LINE_NUM_INDENT}{}\n",
+                lines_3[1]
+            ),
+            format!("{PRE_LINE_NUM_INDENT}9{POST_LINE_NUM_INDENT}{}", lines_3[2]),
+        ];
+
+        assert_eq!(str::from_utf8(&out).unwrap(), expected_out.join(""));
+        Ok(())
+    }
+
+    #[test]
+    fn line_ends_correct_out() -> io::Result<()> {
+        let ins = vec![
+            named("first.txt", INPUT_STREAM_1),
+            named("second.txt", INPUT_STREAM_2),
+            named("third.txt", INPUT_STREAM_3),
+        ];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_ends: true,
+                ..Default::default()
+            },
+        )?;
+
+        let (lines_1, lines_2, lines_3) = (
+            INPUT_STREAM_1.lines().collect::<Vec<_>>(),
+            INPUT_STREAM_2.lines().collect::<Vec<_>>(),
+            INPUT_STREAM_3.lines().collect::<Vec<_>>(),
+        );
+
+        let expected_out = vec![
+            format!("{}$\n", lines_1[0]),
+            format!("{}$\n", lines_1[1]),
+            format!("{}{}$\n", lines_1[2], lines_2[0]),
+            format!("{}$\n", lines_2[1]),
+            format!("{}$\n", lines_2[2]),
+            format!("{}$\n", lines_3[0]),
+            format!("{}$\n", lines_3[1]),
+            format!("{}", lines_3[2]),
+        ];
+
+        assert_eq!(str::from_utf8(&out).unwrap(), expected_out.join(""));
+        Ok(())
+    }
+
+    #[test]
+    fn line_ends_with_error_correct_out() -> io::Result<()> {
+        let ins = vec![
+            named("first.txt", INPUT_STREAM_1),
+            Err(ERROR_1.into()),
+            named("second.txt", INPUT_STREAM_2),
+            named("third.txt", INPUT_STREAM_3),
+        ];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_ends: true,
+                ..Default::default()
+            },
+        )?;
+
+        let (lines_1, lines_2, lines_3) = (
+            INPUT_STREAM_1.lines().collect::<Vec<_>>(),
+            INPUT_STREAM_2.lines().collect::<Vec<_>>(),
+            INPUT_STREAM_3.lines().collect::<Vec<_>>(),
+        );
+
+        let expected_out = vec![
+            format!("{}$\n", lines_1[0]),
+            format!("{}$\n", lines_1[1]),
+            format!("{}cat: {ERROR_1}\n", lines_1[2]),
+            format!("{}$\n", lines_2[0]),
+            format!("{}$\n", lines_2[1]),
+            format!("{}$\n", lines_2[2]),
+            format!("{}$\n", lines_3[0]),
+            format!("{}$\n", lines_3[1]),
+            format!("{}", lines_3[2]),
+        ];
+
+        assert_eq!(str::from_utf8(&out).unwrap(), expected_out.join(""));
+        Ok(())
+    }
+
+    #[test]
+    fn line_nums_and_ends_correct_out() -> io::Result<()> {
+        let ins = vec![
+            named("first.txt", INPUT_STREAM_1),
+            named("second.txt", INPUT_STREAM_2),
+            named("third.txt", INPUT_STREAM_3),
+        ];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                line_ends: true,
+                ..Default::default()
+            },
+        )?;
+
+        let (lines_1, lines_2, lines_3) = (
+            INPUT_STREAM_1.lines().collect::<Vec<_>>(),
+            INPUT_STREAM_2.lines().collect::<Vec<_>>(),
+            INPUT_STREAM_3.lines().collect::<Vec<_>>(),
+        );
+
+        let expected_out = vec![
+            format!(
+                "{PRE_LINE_NUM_INDENT}1{POST_LINE_NUM_INDENT}{}$\n",
+                lines_1[0]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}2{POST_LINE_NUM_INDENT}{}$\n",
+                lines_1[1]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}3{POST_LINE_NUM_INDENT}{}{}$\n",
+                lines_1[2], lines_2[0]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}4{POST_LINE_NUM_INDENT}{}$\n",
+                lines_2[1]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}5{POST_LINE_NUM_INDENT}{}$\n",
+                lines_2[2]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}6{POST_LINE_NUM_INDENT}{}$\n",
+                lines_3[0]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}7{POST_LINE_NUM_INDENT}{}$\n",
+                lines_3[1]
+            ),
+            format!("{PRE_LINE_NUM_INDENT}8{POST_LINE_NUM_INDENT}{}", lines_3[2]),
+        ];
+
+        assert_eq!(str::from_utf8(&out).unwrap(), expected_out.join(""));
+        Ok(())
+    }
+
+    #[test]
+    fn line_nums_and_ends_with_error_correct_out() -> io::Result<()> {
+        let ins = vec![
+            named("first.txt", INPUT_STREAM_1),
+            Err(ERROR_1.into()),
+            named("second.txt", INPUT_STREAM_2),
+            named("third.txt", INPUT_STREAM_3),
+        ];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                line_ends: true,
+                ..Default::default()
+            },
+        )?;
+
+        let (lines_1, lines_2, lines_3) = (
+            INPUT_STREAM_1.lines().collect::<Vec<_>>(),
+            INPUT_STREAM_2.lines().collect::<Vec<_>>(),
+            INPUT_STREAM_3.lines().collect::<Vec<_>>(),
+        );
+
+        let expected_out = vec![
+            format!(
+                "{PRE_LINE_NUM_INDENT}1{POST_LINE_NUM_INDENT}{}$\n",
+                lines_1[0]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}2{POST_LINE_NUM_INDENT}{}$\n",
+                lines_1[1]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}3{POST_LINE_NUM_INDENT}{}cat: {ERROR_1}\n",
+                lines_1[2]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}4{POST_LINE_NUM_INDENT}{}$\n",
+                lines_2[0]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}5{POST_LINE_NUM_INDENT}{}$\n",
+                lines_2[1]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}6{POST_LINE_NUM_INDENT}{}$\n",
+                lines_2[2]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}7{POST_LINE_NUM_INDENT}{}$\n",
+                lines_3[0]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}8{POST_LINE_NUM_INDENT}{}$\n",
+                lines_3[1]
+            ),
+            format!("{PRE_LINE_NUM_INDENT}9{POST_LINE_NUM_INDENT}{}", lines_3[2]),
+        ];
+
+        assert_eq!(str::from_utf8(&out).unwrap(), expected_out.join(""));
+        Ok(())
+    }
+
+    #[cfg(feature = "base64")]
+    #[test]
+    fn base64_encode_wraps_correct_out() -> io::Result<()> {
+        let ins = vec![named("first.txt", INPUT_STREAM_1)];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                transform: crate::OutputTransform::Base64Encode { wrap: 20 },
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "VGhpcyBpcyB0aGUgZmly\nc3QgZmlsZS4uLgpTZWNv\nbmQgbGluZSBvZiBmaXJz\n\
+             dCBmaWxlIG5vdwpOb3Qg\nZW5kaW5nIHdpdGggYSBu\nZXcgbGluZQ==\n"
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "base64")]
+    #[test]
+    fn base64_round_trip_correct_out() -> io::Result<()> {
+        let ins = vec![
+            named("first.txt", INPUT_STREAM_1),
+            named("second.txt", INPUT_STREAM_2),
+            named("third.txt", INPUT_STREAM_3),
+        ];
+        let mut encoded = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut encoded,
+            &mut io::sink(),
+            &CatOptions {
+                transform: crate::OutputTransform::Base64Encode { wrap: 76 },
+                ..Default::default()
+            },
+        )?;
+
+        let ins = vec![named("input", encoded)];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                transform: crate::OutputTransform::Base64Decode,
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            format!("{INPUT_STREAM_1}{INPUT_STREAM_2}{INPUT_STREAM_3}")
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "base64")]
+    #[test]
+    fn base64_decode_invalid_error_out() -> io::Result<()> {
+        let ins = vec![named("input", "SGVsbG8s\n"), named("input", "IHdvc*ZA==\n")];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                transform: crate::OutputTransform::Base64Decode,
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "Hello,cat: invalid base64 input: Invalid symbol 42, offset 5.\n"
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "checksum")]
+    #[test]
+    fn sha256_checksum_covers_line_ends() -> io::Result<()> {
+        use crate::{ChecksumAlgo, ChecksumWriter};
+
+        let ins = vec![
+            named("first.txt", INPUT_STREAM_1),
+            named("second.txt", INPUT_STREAM_2),
+            named("third.txt", INPUT_STREAM_3),
+        ];
+        let mut out = ChecksumWriter::new(Vec::<u8>::default(), ChecksumAlgo::Sha256);
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_ends: true,
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(
+            out.hex_digest(),
+            "9928232a79c7eec8450e9e6e14317541ab1d511a84f81cf93aac08da79b2e022"
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "checksum")]
+    #[test]
+    fn crc32_checksum_correct_out() -> io::Result<()> {
+        use crate::{ChecksumAlgo, ChecksumWriter};
+
+        let ins = vec![
+            named("first.txt", INPUT_STREAM_1),
+            named("second.txt", INPUT_STREAM_2),
+            named("third.txt", INPUT_STREAM_3),
+        ];
+        let mut out = ChecksumWriter::new(Vec::<u8>::default(), ChecksumAlgo::Crc32);
+        cat(ins, &mut out, &mut io::sink(), &CatOptions::default())?;
+
+        assert_eq!(
+            str::from_utf8(&out.inner).unwrap(),
+            format!("{INPUT_STREAM_1}{INPUT_STREAM_2}{INPUT_STREAM_3}")
+        );
+        assert_eq!(out.hex_digest(), "344cde2f");
+
+        // The digest comes back in the stats, not on standard error
+        let stats = crate::Concatenator::default()
+            .add_reader(Cursor::new(format!(
+                "{INPUT_STREAM_1}{INPUT_STREAM_2}{INPUT_STREAM_3}"
+            )))
+            .with_checksum(ChecksumAlgo::Crc32)
+            .concatenate_into(&mut io::sink())?;
+        assert_eq!(stats.checksum.as_deref(), Some("344cde2f"));
+        Ok(())
+    }
+
+    #[cfg(feature = "flate2")]
+    #[test]
+    fn gzip_output_round_trips() -> io::Result<()> {
+        use crate::Concatenator;
+        use flate2::read::GzDecoder;
+        use std::{fs, io::Read};
+
+        let dir = TempDir::new("gzip")?;
+        let path = dir.join("out.gz");
+        Concatenator::default()
+            .add_reader(Cursor::new(INPUT_STREAM_1))
+            .add_reader(Cursor::new(INPUT_STREAM_2))
+            .with_line_numbers()
+            .with_gzip_output()
+            .concatenate_to(fs::File::create(&path)?)?;
+
+        let mut decompressed = String::new();
+        GzDecoder::new(fs::File::open(&path)?).read_to_string(&mut decompressed)?;
+
+        let mut expected = Vec::<u8>::default();
+        cat(
+            vec![
+                named("first.txt", INPUT_STREAM_1),
+                named("second.txt", INPUT_STREAM_2),
+            ],
+            &mut expected,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(decompressed.as_bytes(), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn write_to_path_creates_dirs() -> io::Result<()> {
+        use crate::Concatenator;
+        use std::fs;
+
+        let dir = TempDir::new("write-to-path")?;
+        let path = dir.join("nested").join("out.txt");
+        let catter = || Concatenator::default().add_reader(Cursor::new(INPUT_STREAM_1));
+
+        let e = catter().write_to_path(&path).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::NotFound);
+        assert!(e.to_string().starts_with(&path.display().to_string()));
+
+        catter().with_create_dirs().write_to_path(&path)?;
+        let written = fs::read_to_string(&path)?;
+        assert_eq!(written, INPUT_STREAM_1);
+        Ok(())
+    }
+
+    #[test]
+    fn number_state_file_carries_numbering_on() -> io::Result<()> {
+        use crate::Concatenator;
+        use std::fs;
+
+        let dir = TempDir::new("number-state")?;
+        let state = dir.join("state");
+        let run = |content: &'static str| -> io::Result<String> {
+            let mut out = Vec::<u8>::default();
+            Concatenator::default()
+                .add_reader(Cursor::new(content))
+                .with_line_numbers()
+                .with_number_state_file(&state)
+                .concatenate_into(&mut out)?;
+            Ok(String::from_utf8(out).unwrap())
+        };
+
+        let first = run("a\nb\n")?;
+        let saved = fs::read_to_string(&state)?;
+        let second = run("c\n")?;
+        let resaved = fs::read_to_string(&state)?;
+        fs::write(&state, "not a number")?;
+        let e = run("d\n").unwrap_err();
+        fs::write(&state, "0\n")?;
+        let zero = run("e\n").unwrap_err();
+
+        assert_eq!(first, "     1\ta\n     2\tb\n");
+        assert_eq!(saved, "3\n");
+        assert_eq!(second, "     3\tc\n");
+        assert_eq!(resaved, "4\n");
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(zero.kind(), io::ErrorKind::InvalidData);
+        Ok(())
+    }
+
+    #[test]
+    fn output_stderr_writes_to_stderr() -> io::Result<()> {
+        use crate::Concatenator;
+
+        let catter = || {
+            Concatenator::default()
+                .add_reader(Cursor::new("to standard error\n"))
+                .with_line_numbers()
+        };
+        let (mut stdout, mut stderr) = (Vec::<u8>::default(), Vec::<u8>::default());
+        catter()
+            .with_output_stderr()
+            .concatenate_to_either(&mut stdout, &mut stderr)?;
+        assert!(stdout.is_empty());
+        assert_eq!(stderr, b"     1\tto standard error\n");
+
+        let (mut stdout, mut stderr) = (Vec::<u8>::default(), Vec::<u8>::default());
+        catter().concatenate_to_either(&mut stdout, &mut stderr)?;
+        assert_eq!(stdout, b"     1\tto standard error\n");
+        assert!(stderr.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn auto_number_width_fits_last_line() -> io::Result<()> {
+        use crate::{Concatenator, InputSource};
+        use std::fs;
+
+        let dir = TempDir::new("auto-width")?;
+        let path = dir.join("lines.txt");
+        let content: String = (1..=1234).map(|n| format!("line {n}\n")).collect();
+        fs::write(&path, content)?;
+
+        let catter = || Concatenator::new(vec![InputSource::File(path.display().to_string())]);
+        let mut out = Vec::<u8>::default();
+        catter()
+            .with_line_numbers()
+            .with_auto_number_width()
+            .concatenate_into(&mut out)?;
+        // Standard input can't be counted up front
+        let mut unsized_out = Vec::<u8>::default();
+        Concatenator::new(vec![
+            InputSource::File(path.display().to_string()),
+            InputSource::Reader(Box::new(Cursor::new("more\n"))),
+        ])
+        .with_line_numbers()
+        .with_auto_number_width()
+        .concatenate_into(&mut unsized_out)?;
+
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines.len(), 1234);
+        for (n, line) in lines.iter().enumerate() {
+            assert_eq!(*line, format!("{:>4}\tline {}", n + 1, n + 1));
+        }
+        assert!(unsized_out.starts_with(b"     1\tline 1\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn count_lines_only_plain_inputs() {
+        use super::{count_lines, CatOptions, Encoding, InputSource};
+
+        let bytes = || vec![InputSource::Bytes(b"a\0b\0c".to_vec())];
+        let options = CatOptions {
+            record_separator: b'\0',
+            ..CatOptions::default()
+        };
+        assert_eq!(count_lines(&bytes(), &options), Some(3));
+        assert_eq!(count_lines(&bytes(), &CatOptions::default()), Some(1));
+        let latin1 = CatOptions {
+            input_encoding: Encoding::Latin1,
+            ..CatOptions::default()
+        };
+        assert_eq!(count_lines(&bytes(), &latin1), None);
+        let missing = vec![InputSource::File("/nonexistent/cat-count".to_string())];
+        assert_eq!(count_lines(&missing, &CatOptions::default()), None);
+        let dir = vec![InputSource::File(
+            std::env::temp_dir().display().to_string(),
+        )];
+        assert_eq!(count_lines(&dir, &CatOptions::default()), None);
+    }
+
+    #[test]
+    fn reverse_lines_correct_out() -> io::Result<()> {
+        let ins = vec![
+            named("first.txt", INPUT_STREAM_1),
+            named("second.txt", INPUT_STREAM_2),
+        ];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                line_ends: true,
+                reverse_lines: true,
+                ..Default::default()
+            },
+        )?;
+
+        let expected_out = [
+            format!("{PRE_LINE_NUM_INDENT}1{POST_LINE_NUM_INDENT}...elif tsrif eht si sihT$\n"),
+            format!("{PRE_LINE_NUM_INDENT}2{POST_LINE_NUM_INDENT}won elif tsrif fo enil dnoceS$\n"),
+            format!(
+                "{PRE_LINE_NUM_INDENT}3{POST_LINE_NUM_INDENT}\
+                 ...elif dnoces eht si sihTenil wen a htiw gnidne toN$\n"
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}4{POST_LINE_NUM_INDENT}won elif dnoces fo enil dnoceS$\n"
+            ),
+            format!("{PRE_LINE_NUM_INDENT}5{POST_LINE_NUM_INDENT}enil wen a htiw dne ot gnioG$\n"),
+        ];
+
+        assert_eq!(str::from_utf8(&out).unwrap(), expected_out.join(""));
+        Ok(())
+    }
+
+    #[test]
+    fn reverse_lines_multibyte_correct_out() -> io::Result<()> {
+        let ins = vec![
+            named("input", "Caf\u{e9} \u{1f600}!\r\n"),
+            named("input", "\u{1f980} r\u{fc}st"),
+        ];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                reverse_lines: true,
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "!\u{1f600} \u{e9}faC\r\nts\u{fc}r \u{1f980}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn error_prefix_correct_out() -> io::Result<()> {
+        let ins = vec![
+            named("second.txt", INPUT_STREAM_2),
+            Err(ERROR_1.into()),
+            named("third.txt", INPUT_STREAM_3),
+        ];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                error_prefix: "mytool".to_string(),
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            format!("{INPUT_STREAM_2}mytool: {ERROR_1}\n{INPUT_STREAM_3}")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn number_matching_lines() -> io::Result<()> {
+        use crate::LinePattern;
+
+        let ins = vec![
+            named("first.txt", "alpha\nERROR: one\nbeta\n"),
+            named("second.txt", "gamma\nan ERROR: two"),
+        ];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                number_matching: Some(LinePattern::Substring("ERROR:".to_string())),
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "alpha\n     1\tERROR: one\nbeta\ngamma\n     2\tan ERROR: two"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn blank_line_marker_in_place_of_number() -> io::Result<()> {
+        let ins = vec![named("blanks.txt", "one\n\ntwo\n\r\n\nthree\n")];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                blank_line_marker: Some("-".to_string()),
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "     1\tone\n     -\t\n     3\ttwo\n     -\t\r\n     -\t\n     6\tthree\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn line_number_cap_wraps_or_stops() -> io::Result<()> {
+        use crate::CapMode;
+
+        for (mode, expected) in [
+            (CapMode::Wrap, "  1\ta\n  2\tb\n  3\tc\n  1\td\n  2\te\n"),
+            (CapMode::Stop, "  1\ta\n  2\tb\n  3\tc\n   \td\n   \te\n"),
+        ] {
+            let ins = vec![
+                named("first.txt", "a\nb\n"),
+                named("second.txt", "c\nd\ne\n"),
+            ];
+            let mut out = Vec::<u8>::default();
+            cat(
+                ins,
+                &mut out,
+                &mut io::sink(),
+                &CatOptions {
+                    line_numbers: true,
+                    line_number_width: 3,
+                    line_number_cap: Some((3, mode)),
+                    ..Default::default()
+                },
+            )?;
+
+            assert_eq!(str::from_utf8(&out).unwrap(), expected);
+        }
+
+        // Numbering can't start before 1
+        let e = cat(
+            vec![named("first.txt", "a\n")],
+            &mut io::sink(),
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                line_number_cap: Some((3, CapMode::Wrap)),
+                first_line_number: 0,
+                ..Default::default()
+            },
+        )
+        .unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        Ok(())
+    }
+
+    #[test]
+    fn line_number_every_fifth() -> io::Result<()> {
+        let text: String = (1..=12).map(|i| format!("line {i}\n")).collect();
+        let ins = vec![named("twelve.txt", &text)];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                line_number_every: 5,
+                ..Default::default()
+            },
+        )?;
+
+        let expected = "line 1\nline 2\nline 3\nline 4\n     5\tline 5\n\
+            line 6\nline 7\nline 8\nline 9\n    10\tline 10\nline 11\nline 12\n";
+        assert_eq!(str::from_utf8(&out).unwrap(), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn line_number_every_fifth_with_format() -> io::Result<()> {
+        let text: String = (1..=12).map(|i| format!("line {i}\n")).collect();
+        let ins = vec![named("twelve.txt", &text)];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                line_number_every: 5,
+                number_format: crate::NumberFormat::Hex,
+                number_prefix: true,
+                number_style: crate::NumberStyle::LeftColon,
+                ..Default::default()
+            },
+        )?;
+
+        let numbered: Vec<_> = str::from_utf8(&out)
+            .unwrap()
+            .lines()
+            .filter(|line| line.starts_with("0x"))
+            .collect();
+        assert_eq!(numbered, ["0x5: line 5", "0xa: line 10"]);
+        Ok(())
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn number_matching_regex() -> io::Result<()> {
+        use crate::LinePattern;
+
+        let ins = vec![named("first.txt", "a1\nb\nc22\nd\ne\n")];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                number_matching: Some(LinePattern::Regex(
+                    regex::bytes::Regex::new("[0-9]$").unwrap(),
+                )),
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "     1\ta1\nb\n     2\tc22\nd\ne\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn number_formats() -> io::Result<()> {
+        use crate::NumberFormat::{Dec, Hex, Oct};
+
+        let content = "x\n".repeat(17);
+        for (format, prefix, tenth, seventeenth) in [
+            (Dec, false, "    10", "    17"),
+            (Hex, false, "     a", "    11"),
+            (Hex, true, "   0xa", "  0x11"),
+            (Oct, false, "    12", "    21"),
+            (Oct, true, "  0o12", "  0o21"),
+        ] {
+            let mut out = Vec::<u8>::default();
+            cat(
+                vec![named("in.txt", content.as_str())],
+                &mut out,
+                &mut io::sink(),
+                &CatOptions {
+                    line_numbers: true,
+                    number_format: format,
+                    number_prefix: prefix,
+                    ..Default::default()
+                },
+            )?;
+
+            let out = str::from_utf8(&out).unwrap();
+            let lines = out.lines().collect::<Vec<_>>();
+            assert_eq!(lines[9], format!("{tenth}\tx"));
+            assert_eq!(lines[16], format!("{seventeenth}\tx"));
+        }
+
+        // Hex crossing from 0xf to 0x10
+        let mut out = Vec::<u8>::default();
+        cat(
+            vec![named("in.txt", content.as_str())],
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                number_format: Hex,
+                ..Default::default()
+            },
+        )?;
+        let out = str::from_utf8(&out).unwrap();
+        assert!(out.contains("     f\tx\n    10\tx\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn line_number_width_overflows() -> io::Result<()> {
+        let input = "line\n".repeat(1001);
+        let ins = vec![named("input", input)];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                line_number_width: 3,
+                ..Default::default()
+            },
+        )?;
+
+        let out = str::from_utf8(&out).unwrap();
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 1001);
+        assert_eq!(lines[0], "  1\tline");
+        assert_eq!(lines[98], " 99\tline");
+        assert_eq!(lines[998], "999\tline");
+        assert_eq!(lines[999], "1000\tline");
+        assert_eq!(lines[1000], "1001\tline");
+        Ok(())
+    }
+
+    #[test]
+    fn line_number_width_8_correct_out() -> io::Result<()> {
+        let ins = vec![named("second.txt", INPUT_STREAM_2)];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                line_number_width: 8,
+                ..Default::default()
+            },
+        )?;
+
+        let expected_out = INPUT_STREAM_2
+            .lines()
+            .enumerate()
+            .map(|(i, line)| format!("{:>8}{POST_LINE_NUM_INDENT}{line}\n", i + 1))
+            .collect::<String>();
+
+        assert_eq!(str::from_utf8(&out).unwrap(), expected_out);
+        assert!(str::from_utf8(&out).unwrap().starts_with("       1\t"));
+        Ok(())
+    }
+
+    #[test]
+    fn per_file_numbering_correct_out() -> io::Result<()> {
+        let ins = vec![
+            named("second.txt", INPUT_STREAM_2),
+            named("first.txt", INPUT_STREAM_1),
+            named("third.txt", INPUT_STREAM_3),
+        ];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                per_file_numbering: true,
+                ..Default::default()
+            },
+        )?;
+
+        let (lines_1, lines_2, lines_3) = (
+            INPUT_STREAM_1.lines().collect::<Vec<_>>(),
+            INPUT_STREAM_2.lines().collect::<Vec<_>>(),
+            INPUT_STREAM_3.lines().collect::<Vec<_>>(),
+        );
+
+        let expected_out = [
+            format!(
+                "{PRE_LINE_NUM_INDENT}1{POST_LINE_NUM_INDENT}{}\n",
+                lines_2[0]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}2{POST_LINE_NUM_INDENT}{}\n",
+                lines_2[1]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}3{POST_LINE_NUM_INDENT}{}\n",
+                lines_2[2]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}1{POST_LINE_NUM_INDENT}{}\n",
+                lines_1[0]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}2{POST_LINE_NUM_INDENT}{}\n",
+                lines_1[1]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}3{POST_LINE_NUM_INDENT}{}{}\n",
+                lines_1[2], lines_3[0]
+            ),
+            format!(
+                "{PRE_LINE_NUM_INDENT}2{POST_LINE_NUM_INDENT}{}\n",
+                lines_3[1]
+            ),
+            format!("{PRE_LINE_NUM_INDENT}3{POST_LINE_NUM_INDENT}{}", lines_3[2]),
+        ];
+
+        assert_eq!(str::from_utf8(&out).unwrap(), expected_out.join(""));
+        Ok(())
+    }
+
+    #[test]
+    fn carriage_returns_shown() -> io::Result<()> {
+        for (line_ends, expected) in [
+            (false, "10%^M50%^M100%\ndone^M\nlast^M"),
+            (true, "10%^M50%^M100%$\ndone^M$\nlast^M"),
+        ] {
+            let ins = vec![named("progress.txt", "10%\r50%\r100%\ndone\r\nlast\r")];
+            let mut out = Vec::<u8>::default();
+            cat(
+                ins,
+                &mut out,
+                &mut io::sink(),
+                &CatOptions {
+                    show_carriage_returns: true,
+                    line_ends,
+                    ..Default::default()
+                },
+            )?;
+
+            assert_eq!(str::from_utf8(&out).unwrap(), expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn quiet_writes_only_content() -> io::Result<()> {
+        let ins = vec![
+            named("first.txt", "one\n"),
+            Err(Box::from("oops")),
+            named("second.txt", "two\r\n"),
+        ];
+        let mut out = Vec::<u8>::default();
+        let mut err = Vec::<u8>::default();
+        let stats = cat(
+            ins,
+            &mut out,
+            &mut err,
+            &CatOptions {
+                quiet: true,
+                warn_mixed_endings: true,
+                summary: true,
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(str::from_utf8(&out).unwrap(), "one\ntwo\r\n");
+        assert!(err.is_empty());
+        assert_eq!(stats.errors.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn file_separator_between_inputs() -> io::Result<()> {
+        let options = CatOptions {
+            file_separator: Some("---".to_string()),
+            ..Default::default()
+        };
+
+        let ins = vec![
+            named("first.txt", "a\n"),
+            named("second.txt", "b\n"),
+            named("third.txt", "c"),
+        ];
+        let mut out = Vec::<u8>::default();
+        cat(ins, &mut out, &mut io::sink(), &options)?;
+        assert_eq!(str::from_utf8(&out).unwrap(), "a\n---\nb\n---\nc");
+
+        // Nothing separates failed or empty inputs, and an input without a
+        // trailing newline is ended before the separator
+        let ins = vec![
+            Err(Box::from("first")),
+            named("a.txt", "a"),
+            Err(Box::from("second")),
+            named("empty.txt", ""),
+            named("b.txt", "b\n"),
+        ];
+        let mut out = Vec::<u8>::default();
+        cat(ins, &mut out, &mut io::sink(), &options)?;
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "cat: first\nacat: second\n---\nb\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn line_break_forced_between_files() -> io::Result<()> {
+        for (forced, expected) in [
+            (false, "     1\ta\n     2\tbc\n     3\td"),
+            (true, "     1\ta\n     2\tb\n     3\tc\n     4\td"),
+        ] {
+            let ins = vec![
+                named("first.txt", "a\nb"),
+                named("empty.txt", ""),
+                named("second.txt", "c\nd"),
+            ];
+            let mut out = Vec::<u8>::default();
+            cat(
+                ins,
+                &mut out,
+                &mut io::sink(),
+                &CatOptions {
+                    line_numbers: true,
+                    force_line_break_between_files: forced,
+                    ..Default::default()
+                },
+            )?;
+
+            assert_eq!(str::from_utf8(&out).unwrap(), expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn numbering_after_error_mid_line() -> io::Result<()> {
+        for (continuation, expected) in [
+            (
+                false,
+                "     1\tone\n     2\ttwcat: oops\n     3\to\n     4\tthree\n",
+            ),
+            (true, "     1\tone\n     2\ttwcat: oops\no\n     3\tthree\n"),
+        ] {
+            let ins = vec![
+                named("first.txt", "one\ntw"),
+                Err(Box::from("oops")),
+                named("second.txt", "o\nthree\n"),
+            ];
+            let mut out = Vec::<u8>::default();
+            cat(
+                ins,
+                &mut out,
+                &mut io::sink(),
+                &CatOptions {
+                    line_numbers: true,
+                    number_blank_as_continuation: continuation,
+                    ..Default::default()
+                },
+            )?;
+
+            assert_eq!(str::from_utf8(&out).unwrap(), expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn per_file_numbering_with_error_correct_out() -> io::Result<()> {
+        let ins = vec![
+            named("second.txt", INPUT_STREAM_2),
+            Err(ERROR_1.into()),
+            named("second.txt", INPUT_STREAM_2),
+        ];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                per_file_numbering: true,
+                ..Default::default()
+            },
+        )?;
+
+        let numbered = INPUT_STREAM_2
+            .lines()
+            .enumerate()
+            .map(|(i, line)| {
+                format!(
+                    "{PRE_LINE_NUM_INDENT}{}{POST_LINE_NUM_INDENT}{line}\n",
+                    i + 1
+                )
+            })
+            .collect::<String>();
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            format!("{numbered}cat: {ERROR_1}\n{numbered}")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn filename_prefix_correct_out() -> io::Result<()> {
+        let ins = vec![
+            named("first.txt", INPUT_STREAM_1),
+            named("second.txt", INPUT_STREAM_2),
+            Err(ERROR_1.into()),
+            Ok(Input::stdin(Cursor::new(INPUT_STREAM_3))),
+        ];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                filename_prefix: true,
+                ..Default::default()
+            },
+        )?;
+
+        let (lines_1, lines_2, lines_3) = (
+            INPUT_STREAM_1.lines().collect::<Vec<_>>(),
+            INPUT_STREAM_2.lines().collect::<Vec<_>>(),
+            INPUT_STREAM_3.lines().collect::<Vec<_>>(),
+        );
+
+        let expected_out = [
+            format!(
+                "first.txt:{PRE_LINE_NUM_INDENT}1{POST_LINE_NUM_INDENT}{}\n",
+                lines_1[0]
+            ),
+            format!(
+                "first.txt:{PRE_LINE_NUM_INDENT}2{POST_LINE_NUM_INDENT}{}\n",
                 lines_1[1]
             ),
-            format!(
-                "{PRE_LINE_NUM_INDENT}3{POST_LINE_NUM_INDENT}{}{}\n",
-                lines_1[2], lines_2[0]
+            format!(
+                "first.txt:{PRE_LINE_NUM_INDENT}3{POST_LINE_NUM_INDENT}{}{}\n",
+                lines_1[2], lines_2[0]
+            ),
+            format!(
+                "second.txt:{PRE_LINE_NUM_INDENT}4{POST_LINE_NUM_INDENT}{}\n",
+                lines_2[1]
+            ),
+            format!(
+                "second.txt:{PRE_LINE_NUM_INDENT}5{POST_LINE_NUM_INDENT}{}\n",
+                lines_2[2]
+            ),
+            format!("cat: {ERROR_1}\n"),
+            format!(
+                "(standard input):{PRE_LINE_NUM_INDENT}6{POST_LINE_NUM_INDENT}{}\n",
+                lines_3[0]
+            ),
+            format!(
+                "(standard input):{PRE_LINE_NUM_INDENT}7{POST_LINE_NUM_INDENT}{}\n",
+                lines_3[1]
+            ),
+            format!(
+                "(standard input):{PRE_LINE_NUM_INDENT}8{POST_LINE_NUM_INDENT}{}",
+                lines_3[2]
+            ),
+        ];
+
+        assert_eq!(str::from_utf8(&out).unwrap(), expected_out.join(""));
+        Ok(())
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn json_array_of_lines() -> io::Result<()> {
+        use crate::OutputFormat;
+
+        for (inputs, expected) in [
+            (vec![], "[]"),
+            (vec![""], "[]"),
+            (vec!["one"], r#"[{"file":"a.txt","line":1,"text":"one"}]"#),
+            (
+                vec!["one\ntwo\n", "three\n"],
+                r#"[{"file":"a.txt","line":1,"text":"one"},{"file":"a.txt","line":2,"text":"two"},{"file":"a.txt","line":3,"text":"three"}]"#,
+            ),
+        ] {
+            let ins = inputs
+                .into_iter()
+                .map(|input| named("a.txt", input))
+                .collect();
+            let mut out = Vec::<u8>::default();
+            let options = CatOptions {
+                format: OutputFormat::Json,
+                ..Default::default()
+            };
+            cat(ins, &mut out, &mut io::sink(), &options)?;
+            let out = str::from_utf8(&out).unwrap();
+            assert_eq!(out, format!("{expected}\n"));
+            serde_json::from_str::<serde_json::Value>(out).unwrap();
+        }
+
+        // An input that fails is reported apart from the array
+        let ins = vec![
+            named("a.txt", "one\n"),
+            Err(ERROR_1.into()),
+            named("a.txt", "two\n"),
+        ];
+        let (mut out, mut err) = (Vec::<u8>::default(), Vec::<u8>::default());
+        let options = CatOptions {
+            format: OutputFormat::Json,
+            ..Default::default()
+        };
+        let stats = cat(ins, &mut out, &mut err, &options)?;
+        let records: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(records.as_array().map(Vec::len), Some(2));
+        assert_eq!(str::from_utf8(&err).unwrap(), format!("cat: {ERROR_1}\n"));
+        assert_eq!(stats.errors.len(), 1);
+        Ok(())
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn ndjson_escapes_correct_out() -> io::Result<()> {
+        use crate::OutputFormat;
+
+        let ins = vec![
+            named("a.txt", "say \"hi\"\tthere\nC:\\path\\to\n"),
+            Err(ERROR_1.into()),
+            named("b.txt", "bell\u{7}\nno newline"),
+            Ok(Input::stdin(Cursor::new("from stdin\n"))),
+            Ok(Input::stdin(Cursor::new("last"))),
+        ];
+        let mut out = Vec::<u8>::default();
+        let mut err = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut err,
+            &CatOptions {
+                per_file_numbering: true,
+                format: OutputFormat::Ndjson,
+                ..Default::default()
+            },
+        )?;
+
+        let expected_out = [
+            r#"{"file":"a.txt","line":1,"text":"say \"hi\"\tthere"}"#,
+            r#"{"file":"a.txt","line":2,"text":"C:\\path\\to"}"#,
+            r#"{"file":"b.txt","line":1,"text":"bell\u0007"}"#,
+            r#"{"file":"b.txt","line":2,"text":"no newlinefrom stdin"}"#,
+            r#"{"file":null,"line":1,"text":"last"}"#,
+        ];
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            expected_out.map(|line| format!("{line}\n")).join("")
+        );
+        // Errors go with the warnings, leaving a record on every line
+        assert_eq!(str::from_utf8(&err).unwrap(), format!("cat: {ERROR_1}\n"));
+        for line in str::from_utf8(&out).unwrap().lines() {
+            serde_json::from_str::<serde_json::Value>(line).unwrap();
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn expand_tabs_8_correct_out() -> io::Result<()> {
+        let ins = vec![
+            named("first.txt", "a\tbc\tdef\n\t\u{e9}t\u{e9}\tx"),
+            named("second.txt", "\tmerged\n12345678\tnine\n"),
+        ];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                tabstop: Some(8),
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "a       bc      def\n        \u{e9}t\u{e9}     x       merged\n12345678        nine\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn expand_tabs_4_with_line_nums_correct_out() -> io::Result<()> {
+        let ins = vec![named("first.txt", "a\tbc\tdef\tg\n\t\tx\n")];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                tabstop: Some(4),
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "     1  a   bc  def g\n     2          x\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn trim_trailing_whitespace_correct_out() -> io::Result<()> {
+        let ins = vec![
+            named("first.txt", "spaces   \ntabs\t\t\nmixed \t \t\r\n"),
+            named("second.txt", "  \t\n\tindented, untouched\nlast line \t"),
+        ];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_ends: true,
+                trim_trailing_whitespace: true,
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "spaces$\ntabs$\nmixed^M$\n$\n\tindented, untouched$\nlast line"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn latin1_input_correct_out() -> io::Result<()> {
+        use crate::Encoding;
+
+        let ins = vec![named("latin1.txt", b"caf\xE9\n\xA3\xFF".to_vec())];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                line_ends: true,
+                input_encoding: Encoding::Latin1,
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(
+            out,
+            b"     1\tcaf\xC3\xA9$\n     2\t\xC2\xA3\xC3\xBF".to_vec()
+        );
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "     1\tcaf\u{e9}$\n     2\t\u{a3}\u{ff}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn skip_binary_warns() -> io::Result<()> {
+        let ins = vec![
+            named("first.txt", INPUT_STREAM_2.as_bytes()),
+            named("binary.bin", b"\x7fELF\x02\x01\x01\x00\x00\n"),
+            named("third.txt", INPUT_STREAM_3.as_bytes()),
+        ];
+        let mut out = Vec::<u8>::default();
+        let mut err = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut err,
+            &CatOptions {
+                skip_binary: true,
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            format!("{INPUT_STREAM_2}{INPUT_STREAM_3}")
+        );
+        assert_eq!(
+            str::from_utf8(&err).unwrap(),
+            "cat: binary.bin: binary file, skipping\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn skip_binary_forced_correct_out() -> io::Result<()> {
+        let ins = vec![
+            named("binary.bin", &b"\x7fELF\x02\x01\x01\x00\x00\n"[..]),
+            named("third.txt", INPUT_STREAM_3.as_bytes()),
+        ];
+        let mut out = Vec::<u8>::default();
+        let mut err = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut err,
+            &CatOptions {
+                skip_binary: true,
+                force: true,
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(
+            out,
+            [b"\x7fELF\x02\x01\x01\x00\x00\n", INPUT_STREAM_3.as_bytes()].concat()
+        );
+        assert!(err.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn max_line_width_truncate_correct_out() -> io::Result<()> {
+        use crate::WrapMode;
+
+        for (ellipsis, expected_out) in [
+            (false, "short$\nexactly10!$\n12345678\u{e9}0$\nshort^M$\n"),
+            (
+                true,
+                "short$\nexactly10!$\n12345678\u{e9}\u{2026}$\nshort^M$\n",
+            ),
+        ] {
+            let ins = vec![named(
+                "first.txt",
+                "short\nexactly10!\n12345678\u{e9}0abcdef\nshort\r\n",
+            )];
+            let mut out = Vec::<u8>::default();
+            cat(
+                ins,
+                &mut out,
+                &mut io::sink(),
+                &CatOptions {
+                    line_ends: true,
+                    max_line_width: Some((10, WrapMode::Truncate { ellipsis })),
+                    ..Default::default()
+                },
+            )?;
+
+            assert_eq!(str::from_utf8(&out).unwrap(), expected_out);
+        }
+
+        // With no columns, every line is left empty
+        for ellipsis in [false, true] {
+            let ins = vec![named("first.txt", "short\n\nx")];
+            let mut out = Vec::<u8>::default();
+            cat(
+                ins,
+                &mut out,
+                &mut io::sink(),
+                &CatOptions {
+                    line_ends: true,
+                    max_line_width: Some((0, WrapMode::Truncate { ellipsis })),
+                    ..Default::default()
+                },
+            )?;
+            assert_eq!(str::from_utf8(&out).unwrap(), "$\n$\n");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn max_line_width_wrap_correct_out() -> io::Result<()> {
+        use crate::WrapMode;
+
+        let ins = vec![named(
+            "first.txt",
+            "short\n123\u{e9}56789012345678901234\nx",
+        )];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                line_ends: true,
+                max_line_width: Some((10, WrapMode::Wrap)),
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "     1\tshort$\n     2\t123\u{e9}567890\n1234567890\n1234$\n     3\tx"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn prefix_and_suffix_correct_out() -> io::Result<()> {
+        for (last, expected_last) in [
+            (
+                INPUT_STREAM_2,
+                format!(
+                    "{PRE_LINE_NUM_INDENT}3{POST_LINE_NUM_INDENT}Going to end with a new line\n"
+                ),
+            ),
+            (
+                INPUT_STREAM_3,
+                format!("{PRE_LINE_NUM_INDENT}3{POST_LINE_NUM_INDENT}Not ending with a new line"),
+            ),
+        ] {
+            let ins = vec![named("last.txt", last)];
+            let mut out = Vec::<u8>::default();
+            cat(
+                ins,
+                &mut out,
+                &mut io::sink(),
+                &CatOptions {
+                    line_numbers: true,
+                    prefix: Some("```text\n".to_string()),
+                    suffix: Some("```\n".to_string()),
+                    ..Default::default()
+                },
+            )?;
+
+            let out = str::from_utf8(&out).unwrap();
+            assert!(out.starts_with(&format!(
+                "```text\n{PRE_LINE_NUM_INDENT}1{POST_LINE_NUM_INDENT}"
+            )));
+            assert!(out.ends_with(&format!("{expected_last}```\n")));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn prefix_and_suffix_no_ins_correct_out() -> io::Result<()> {
+        let ins = vec![named("empty.txt", ""), Err(ERROR_1.into())];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                prefix: Some("<<".to_string()),
+                suffix: Some(">>".to_string()),
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            format!("<<cat: {ERROR_1}\n>>")
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "syntect")]
+    #[test]
+    fn syntax_highlight_unknown_extension_no_op() -> io::Result<()> {
+        let content = "fn main() {\n    println!(\"hi\");\n}\n";
+        let ins = vec![
+            named("main.unknownext", content),
+            named("notes.txt", content),
+            Ok(Input::stdin(Cursor::new(content))),
+        ];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                syntax_highlight: true,
+                ..Default::default()
+            },
+        )?;
+
+        let mut plain = Vec::<u8>::default();
+        let ins = vec![
+            named("main.unknownext", content),
+            named("notes.txt", content),
+            Ok(Input::stdin(Cursor::new(content))),
+        ];
+        cat(
+            ins,
+            &mut plain,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(out, plain);
+        Ok(())
+    }
+
+    #[test]
+    fn ansi_escapes_stripped() -> io::Result<()> {
+        use super::AnsiStripper;
+        use std::io::{BufRead, Read};
+
+        let colored = "\x1b[1;31merror\x1b[0m: \x1b]0;title\x07disk \x1b[32mfull\x1b[m\n";
+        // A sequence split between two of the reader's buffers
+        let split = Cursor::new(&b"plain \x1b[3"[..]).chain(Cursor::new(&b"4mblue\x1b[0m\n"[..]));
+        let ins = vec![
+            Ok(Input::new(
+                "colored.log",
+                Box::new(Cursor::new(colored)) as Box<dyn BufRead>,
+            )),
+            Ok(Input::new("split.log", Box::new(split))),
+        ];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                strip_ansi: true,
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "error: disk full\nplain blue\n"
+        );
+
+        // Content fed in pieces, as a very long line is, keeps its place
+        // in a sequence cut off between them
+        let mut stripper = AnsiStripper::default();
+        let mut stripped = stripper.strip(b"a\x1b[1;3").into_owned();
+        stripped.extend_from_slice(&stripper.strip(b"1mb\x1b"));
+        stripped.extend_from_slice(&stripper.strip(b"[0mc"));
+        assert_eq!(stripped, b"abc");
+        Ok(())
+    }
+
+    #[test]
+    fn case_folding_ascii() -> io::Result<()> {
+        use crate::CaseFold;
+
+        for (fold, expected) in [
+            (
+                CaseFold::Lower,
+                "hello, world!\nmixed case 42\n\u{c9}t\u{c9}\n",
+            ),
+            (
+                CaseFold::Upper,
+                "HELLO, WORLD!\nMIXED CASE 42\n\u{c9}T\u{c9}\n",
+            ),
+        ] {
+            let ins = vec![named(
+                "mixed.txt",
+                "Hello, World!\nmIxEd CaSe 42\n\u{c9}t\u{c9}\n",
+            )];
+            let mut out = Vec::<u8>::default();
+            cat(
+                ins,
+                &mut out,
+                &mut io::sink(),
+                &CatOptions {
+                    case_fold: Some(fold),
+                    ..Default::default()
+                },
+            )?;
+
+            assert_eq!(str::from_utf8(&out).unwrap(), expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn case_folding_unicode() -> io::Result<()> {
+        use crate::CaseFold;
+
+        for (fold, expected) in [
+            (
+                CaseFold::Lower,
+                "\u{e9}t\u{e9} \u{3c3}\u{3bf}\u{3c6}\u{3af}\u{3b1}\n",
+            ),
+            (
+                CaseFold::Upper,
+                "\u{c9}T\u{c9} \u{3a3}\u{39f}\u{3a6}\u{399}\u{391} STRASSE\n",
+            ),
+        ] {
+            let content = match fold {
+                CaseFold::Lower => "\u{c9}t\u{c9} \u{3a3}\u{3bf}\u{3a6}\u{3af}\u{391}\n",
+                CaseFold::Upper => {
+                    "\u{e9}t\u{e9} \u{3c3}\u{3bf}\u{3c6}\u{3b9}\u{3b1} stra\u{df}e\n"
+                }
+            };
+            let ins = vec![named("unicode.txt", content)];
+            let mut out = Vec::<u8>::default();
+            cat(
+                ins,
+                &mut out,
+                &mut io::sink(),
+                &CatOptions {
+                    case_fold: Some(fold),
+                    unicode_case: true,
+                    ..Default::default()
+                },
+            )?;
+
+            assert_eq!(str::from_utf8(&out).unwrap(), expected);
+        }
+
+        // Bytes that aren't UTF-8 are passed through untouched
+        let ins = vec![named("invalid.txt", b"A\xffB\n".as_slice())];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                case_fold: Some(CaseFold::Lower),
+                unicode_case: true,
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(out, b"a\xffb\n");
+        Ok(())
+    }
+
+    #[test]
+    fn show_ends_crlf() -> io::Result<()> {
+        // A carriage return is only part of the line ending right before a
+        // newline, even when the line is read in several pieces
+        let long = "x".repeat(64 * 1024 - 1);
+        let files = [
+            ("crlf.txt", "windows\r\nprogress\r50%\r\n".to_string()),
+            ("long.txt", format!("{long}\r\n{long}\rx\n")),
+            ("unterminated.txt", "no newline\r".to_string()),
+        ];
+
+        for hold_lines in [false, true] {
+            let ins = files
+                .iter()
+                .map(|(path, content)| named(path, content.as_str()))
+                .collect();
+            let mut out = Vec::<u8>::default();
+            cat(
+                ins,
+                &mut out,
+                &mut io::sink(),
+                &CatOptions {
+                    line_ends: true,
+                    trim_trailing_whitespace: hold_lines,
+                    ..Default::default()
+                },
+            )?;
+
+            assert_eq!(
+                str::from_utf8(&out).unwrap(),
+                format!("windows^M$\nprogress\r50%^M$\n{long}^M$\n{long}\rx$\nno newline\r")
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn nul_bytes_pass_through() -> io::Result<()> {
+        const NUL_STREAM: &[u8] = b"\0a\0\n\0\n\n\0\0b\nend\0";
+
+        let mut out = Vec::<u8>::default();
+        cat(
+            vec![named("nul.bin", NUL_STREAM)],
+            &mut out,
+            &mut io::sink(),
+            &CatOptions::default(),
+        )?;
+        assert_eq!(out, NUL_STREAM);
+
+        let mut out = Vec::<u8>::default();
+        cat(
+            vec![named("nul.bin", NUL_STREAM)],
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                ..Default::default()
+            },
+        )?;
+        let expected = [
+            &b"     1\t\0a\0\n"[..],
+            b"     2\t\0\n",
+            b"     3\t\n",
+            b"     4\t\0\0b\n",
+            b"     5\tend\0",
+        ]
+        .concat();
+        assert_eq!(out, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn numbering_tab_replaced_with_spaces() -> io::Result<()> {
+        let mut options = CatOptions {
+            line_numbers: true,
+            replace_tabs_in_numbering: true,
+            ..Default::default()
+        };
+        let mut out = Vec::<u8>::default();
+        let ins = vec![Ok(Input::stdin(Cursor::new("one\n\ttwo\n")))];
+        cat(ins, &mut out, &mut io::sink(), &options)?;
+        // Tabs in the content are left alone
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "     1  one\n     2  \ttwo\n"
+        );
+
+        options.filename_prefix = true;
+        options.tabstop = Some(4);
+        let mut out = Vec::<u8>::default();
+        let ins = vec![Ok(Input::new("a.txt", Cursor::new("one\n")))];
+        cat(ins, &mut out, &mut io::sink(), &options)?;
+        assert_eq!(str::from_utf8(&out).unwrap(), "a.txt:     1    one\n");
+        Ok(())
+    }
+
+    #[cfg(feature = "unicode-width")]
+    #[test]
+    fn display_width_numbering_pads_with_spaces() -> io::Result<()> {
+        let options = CatOptions {
+            line_numbers: true,
+            display_width_numbering: true,
+            ..Default::default()
+        };
+        let mut out = Vec::<u8>::default();
+        let ins = vec![Ok(Input::stdin(Cursor::new("ascii\n漢字\n")))];
+        cat(ins, &mut out, &mut io::sink(), &options)?;
+        // The padding only depends on what comes before the content
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "     1  ascii\n     2  漢字\n"
+        );
+
+        // A filename of two CJK characters takes up four columns, not two
+        let options = CatOptions {
+            filename_prefix: true,
+            ..options
+        };
+        let mut out = Vec::<u8>::default();
+        let ins = vec![Ok(Input::new("漢字.rs", Cursor::new("漢字\nascii\n")))];
+        cat(ins, &mut out, &mut io::sink(), &options)?;
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "漢字.rs:     1  漢字\n漢字.rs:     2  ascii\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn timestamp_prefix_from_injected_clock() -> io::Result<()> {
+        use std::{
+            sync::Arc,
+            time::{Duration, UNIX_EPOCH},
+        };
+
+        // 2024-02-29 13:05:09 UTC
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_211_909);
+        let ins = vec![named("log.txt", "one\ntwo\n")];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                timestamp_format: Some("[%Y-%m-%d %H:%M:%S %s %q%%]".to_string()),
+                clock: Arc::new(move || time),
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "[2024-02-29 13:05:09 1709211909 %q%]      1\tone\n\
+             [2024-02-29 13:05:09 1709211909 %q%]      2\ttwo\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn source_byte_gutter_restarts_per_input() -> io::Result<()> {
+        let ins = vec![
+            named("first.txt", "one\ntwo\n"),
+            Err(Box::from("oops")),
+            named("second.txt", "three\nfour"),
+            named("third.txt", "\nfive\n"),
+        ];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                source_byte_gutter: true,
+                ..Default::default()
+            },
+        )?;
+
+        // The line merged across the end of second.txt started in it
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "[file 1 @ 0] one\n[file 1 @ 4] two\ncat: oops\n\
+             [file 3 @ 0] three\n[file 3 @ 6] four\n[file 4 @ 1] five\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn byte_offsets_continue_across_inputs() -> io::Result<()> {
+        use crate::NumberFormat;
+
+        let mut options = CatOptions {
+            byte_offsets: true,
+            line_number_width: 3,
+            ..Default::default()
+        };
+        for (format, expected) in [
+            (
+                NumberFormat::Dec,
+                "  0\tone\n  4\ttwo\n  8\tthreefour\n 18\t\n 19\tfive\n",
+            ),
+            (
+                NumberFormat::Hex,
+                "  0\tone\n  4\ttwo\n  8\tthreefour\n 12\t\n 13\tfive\n",
+            ),
+        ] {
+            options.number_format = format;
+            let mut out = Vec::<u8>::default();
+            let ins = vec![
+                Ok(Input::stdin(Cursor::new("one\ntwo\nthree"))),
+                Ok(Input::stdin(Cursor::new("four\n\nfive\n"))),
+            ];
+            cat(ins, &mut out, &mut io::sink(), &options)?;
+            assert_eq!(str::from_utf8(&out).unwrap(), expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn closed_output_stops_cleanly() -> io::Result<()> {
+        // Takes `room` bytes, then fails like a pipe whose reader has gone
+        struct ClosingWriter {
+            written: Vec<u8>,
+            room: usize,
+        }
+
+        impl io::Write for ClosingWriter {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                if self.room == 0 {
+                    return Err(io::ErrorKind::BrokenPipe.into());
+                }
+                let n = buf.len().min(self.room);
+                self.written.extend_from_slice(&buf[..n]);
+                self.room -= n;
+                Ok(n)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut out = ClosingWriter {
+            written: Vec::new(),
+            room: 10,
+        };
+        let ins = vec![
+            Ok(Input::stdin(Cursor::new(INPUT_STREAM_1))),
+            Ok(Input::stdin(Cursor::new(INPUT_STREAM_2))),
+        ];
+        let stats = cat(ins, &mut out, &mut io::sink(), &CatOptions::default())?;
+
+        assert_eq!(out.written, &INPUT_STREAM_1.as_bytes()[..10]);
+        // Nothing more was read once the output was gone
+        assert_eq!(stats.files, 1);
+        assert!(stats.errors.is_empty());
+
+        // Errors found before it closed are still returned, even the one
+        // being written as it did, and so is hitting the output limit
+        let mut out = ClosingWriter {
+            written: Vec::new(),
+            room: 10,
+        };
+        let ins = vec![
+            Err(Box::from(ERROR_1)),
+            Ok(Input::stdin(Cursor::new(INPUT_STREAM_1))),
+        ];
+        let stats = cat(ins, &mut out, &mut io::sink(), &CatOptions::default())?;
+        assert_eq!(stats.errors.len(), 1);
+        assert_eq!(stats.errors[0].to_string(), ERROR_1);
+
+        let mut out = io::BufWriter::new(ClosingWriter {
+            written: Vec::new(),
+            room: 3,
+        });
+        let ins = vec![Ok(Input::stdin(Cursor::new(INPUT_STREAM_1)))];
+        let options = CatOptions {
+            max_total_bytes: Some(5),
+            ..Default::default()
+        };
+        let stats = cat(ins, &mut out, &mut io::sink(), &options)?;
+        assert!(stats.output_limit_reached);
+        assert_eq!(stats.lines, 1);
+
+        // Listing inputs keeps what it found too
+        let mut out = ClosingWriter {
+            written: Vec::new(),
+            room: 10,
+        };
+        let ins = vec![Err(Box::from(ERROR_1)), named("first.txt", "")];
+        let stats = list_inputs(ins, &mut out, &CatOptions::default())?;
+        assert_eq!(stats.errors.len(), 1);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn input_timeout_when_nothing_to_read() -> io::Result<()> {
+        use super::TimeoutReader;
+        use std::{
+            fs::File,
+            io::{Read, Write},
+            os::fd::FromRawFd,
+            time::Duration,
+        };
+
+        let mut fds = [0; 2];
+        // SAFETY: `fds` has room for both ends of the pipe
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        // SAFETY: both descriptors were just opened, and are owned from here
+        let (read_end, mut write_end) =
+            unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+        let mut reader = TimeoutReader::new(read_end, Duration::from_millis(50));
+        let mut buf = [0; 16];
+
+        // Nothing written yet, so reading gives up rather than blocking
+        let e = reader.read(&mut buf).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(e.to_string(), "no input for 50ms");
+
+        write_end.write_all(b"late\n")?;
+        assert_eq!(reader.read(&mut buf)?, 5);
+        assert_eq!(&buf[..5], b"late\n");
+        Ok(())
+    }
+
+    #[test]
+    fn verbose_notes_each_input() -> io::Result<()> {
+        let ins: Vec<Result<_, Box<dyn Error>>> = vec![
+            Ok(Input::new("a.txt", Cursor::new("a\n"))),
+            Err("missing.txt: No such file or directory".into()),
+            Ok(Input::stdin(Cursor::new("b\n"))),
+            Ok(Input::new("c.txt", Cursor::new("c\n"))),
+        ];
+        let options = CatOptions {
+            verbose: true,
+            ..Default::default()
+        };
+        let mut out = Vec::<u8>::default();
+        let mut err = Vec::<u8>::default();
+        cat(ins, &mut out, &mut err, &options)?;
+
+        assert_eq!(
+            str::from_utf8(&err).unwrap(),
+            "cat: reading a.txt\ncat: reading standard input\ncat: reading c.txt\n"
+        );
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "a\ncat: missing.txt: No such file or directory\nb\nc\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn number_styles() -> io::Result<()> {
+        use crate::NumberStyle;
+
+        for (style, expected) in [
+            (NumberStyle::GnuRight, "     1\tone\n     2\ttwo\n"),
+            (NumberStyle::LeftColon, "1: one\n2: two\n"),
+            (
+                NumberStyle::Custom {
+                    separator: " | ".to_string(),
+                    left_align: true,
+                },
+                "1      | one\n2      | two\n",
+            ),
+            (
+                NumberStyle::Custom {
+                    separator: ") ".to_string(),
+                    left_align: false,
+                },
+                "     1) one\n     2) two\n",
+            ),
+        ] {
+            let options = CatOptions {
+                line_numbers: true,
+                number_style: style,
+                ..Default::default()
+            };
+            let mut out = Vec::<u8>::default();
+            let ins = vec![Ok(Input::stdin(Cursor::new("one\ntwo\n")))];
+            cat(ins, &mut out, &mut io::sink(), &options)?;
+            assert_eq!(str::from_utf8(&out).unwrap(), expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn lines_over_max_buffered_written_untransformed() -> io::Result<()> {
+        use crate::LinePattern;
+
+        let options = CatOptions {
+            reverse_lines: true,
+            line_ends: true,
+            max_buffered_line: 10,
+            ..Default::default()
+        };
+        let input = "abcdefghijklmnopqrstuvwxyz\nshort\n0123456789abc\nlast";
+        let mut out = Vec::<u8>::default();
+        let mut err = Vec::<u8>::default();
+        let ins = vec![Ok(Input::stdin(Cursor::new(input)))];
+        cat(ins, &mut out, &mut err, &options)?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "abcdefghijklmnopqrstuvwxyz$\ntrohs$\n0123456789abc$\ntsal"
+        );
+        // Only warned about once
+        assert_eq!(
+            str::from_utf8(&err).unwrap(),
+            "cat: line longer than 10 bytes, written untransformed\n"
+        );
+
+        // Lines needed whole to decide on numbering or dropping them are
+        // held no longer than that either: the long one is numbered, and
+        // its repeat isn't dropped
+        let options = CatOptions {
+            line_numbers: true,
+            number_matching: Some(LinePattern::Substring("x".to_string())),
+            unique_adjacent: true,
+            max_buffered_line: 10,
+            ..Default::default()
+        };
+        let input = "x\nx\nabcdefghijklmnop\nabcdefghijklmnop\nshort\n";
+        let mut out = Vec::<u8>::default();
+        let mut err = Vec::<u8>::default();
+        let ins = vec![Ok(Input::stdin(Cursor::new(input)))];
+        cat(ins, &mut out, &mut err, &options)?;
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "     1\tx\n     2\tabcdefghijklmnop\n     3\tabcdefghijklmnop\nshort\n"
+        );
+        assert_eq!(
+            str::from_utf8(&err).unwrap(),
+            "cat: line longer than 10 bytes, written untransformed\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn bytes_replaced_before_splitting() -> io::Result<()> {
+        use std::collections::HashMap;
+
+        let options = CatOptions {
+            line_numbers: true,
+            line_ends: true,
+            replace_bytes: HashMap::from([
+                (b'\r', vec![]),
+                (b'\t', b"  ".to_vec()),
+                (b'\n', b"<newline>".to_vec()),
+            ]),
+            ..Default::default()
+        };
+        let mut out = Vec::<u8>::default();
+        let ins = vec![Ok(Input::stdin(Cursor::new("a\tb\r\n\tc\r\n")))];
+        cat(ins, &mut out, &mut io::sink(), &options)?;
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "     1\ta  b$\n     2\t  c$\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn input_hooks_called_per_input() -> io::Result<()> {
+        use crate::Concatenator;
+        use std::{cell::RefCell, rc::Rc};
+
+        let started = Rc::new(RefCell::new(Vec::new()));
+        let ended = Rc::new(RefCell::new(Vec::new()));
+        let (started_hook, ended_hook) = (started.clone(), ended.clone());
+        Concatenator::default()
+            .add_reader(Cursor::new("one\n"))
+            .add_reader(Cursor::new(INPUT_STREAM_2))
+            .add_reader(Cursor::new("three\nlines\nhere\n"))
+            .on_input_start(move |desc| started_hook.borrow_mut().push(desc.index))
+            .on_input_end(move |desc, stats| {
+                ended_hook
+                    .borrow_mut()
+                    .push((desc.index, stats.bytes, stats.lines))
+            })
+            .concatenate_into(&mut Vec::<u8>::default())?;
+
+        assert_eq!(*started.borrow(), [0, 1, 2]);
+        assert_eq!(
+            *ended.borrow(),
+            [(0, 4, 1), (1, INPUT_STREAM_2.len() as u64, 3), (2, 17, 3)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn visible_line_endings_over_mixed_input() -> io::Result<()> {
+        for line_ends in [false, true] {
+            let options = CatOptions {
+                line_ends,
+                visible_line_endings: true,
+                ..Default::default()
+            };
+            let mut out = Vec::<u8>::default();
+            let ins = vec![
+                Ok(Input::stdin(Cursor::new("lf\ncrlf\r\ncr\rin\r\n"))),
+                Ok(Input::stdin(Cursor::new("\r\n\nlast\r"))),
+            ];
+            cat(ins, &mut out, &mut io::sink(), &options)?;
+            assert_eq!(
+                str::from_utf8(&out).unwrap(),
+                "lf␊\ncrlf␍␊\ncr\rin␍␊\n␍␊\n␊\nlast\r"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn peek_first_bytes_leaves_them_unread() -> io::Result<()> {
+        use super::peek_first_bytes;
+        use std::io::Read;
+
+        let mut reader = Cursor::new("0123456789");
+        assert_eq!(peek_first_bytes(&mut reader, 4)?, b"0123");
+        assert_eq!(peek_first_bytes(&mut reader, 20)?, b"0123456789");
+
+        let mut read = String::new();
+        reader.read_to_string(&mut read)?;
+        assert_eq!(read, "0123456789");
+        assert_eq!(peek_first_bytes(&mut reader, 4)?, b"");
+        Ok(())
+    }
+
+    #[test]
+    fn max_total_bytes_stops_mid_input() -> io::Result<()> {
+        let ins = vec![
+            named("first.txt", "aaaa\n"),
+            named("second.txt", "bbbb\n"),
+            named("third.txt", "cccc\n"),
+        ];
+        let mut out = Vec::<u8>::default();
+        let mut err = Vec::<u8>::default();
+        let stats = cat(
+            ins,
+            &mut out,
+            &mut err,
+            &CatOptions {
+                max_total_bytes: Some(7),
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(str::from_utf8(&out).unwrap(), "aaaa\nbb");
+        assert_eq!(str::from_utf8(&err).unwrap(), "cat: output limit reached\n");
+        assert!(stats.output_limit_reached);
+        assert_eq!(stats.files, 2);
+
+        // Output that fits isn't cut short
+        let mut out = Vec::<u8>::default();
+        let ins = vec![named("first.txt", "aaaa\n")];
+        let options = CatOptions {
+            max_total_bytes: Some(5),
+            ..Default::default()
+        };
+        let stats = cat(ins, &mut out, &mut io::sink(), &options)?;
+        assert_eq!(out, b"aaaa\n");
+        assert!(!stats.output_limit_reached);
+        Ok(())
+    }
+
+    #[test]
+    fn max_total_bytes_stops_endless_input() -> io::Result<()> {
+        use super::MAX_LINE_CHUNK;
+        use std::{
+            cell::Cell,
+            io::{BufRead, Read},
+            rc::Rc,
+        };
+
+        // Repeats its pattern without end, counting the bytes read from it
+        struct Endless {
+            pattern: &'static [u8],
+            read: Rc<Cell<u64>>,
+        }
+
+        impl Read for Endless {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let n = self.fill_buf()?.read(buf)?;
+                self.consume(n);
+                Ok(n)
+            }
+        }
+
+        impl BufRead for Endless {
+            fn fill_buf(&mut self) -> io::Result<&[u8]> {
+                let start = (self.read.get() % self.pattern.len() as u64) as usize;
+                Ok(&self.pattern[start..])
+            }
+
+            fn consume(&mut self, amt: usize) {
+                self.read.set(self.read.get() + amt as u64);
+            }
+        }
+
+        for (pattern, unique_adjacent) in [(&b"y\n"[..], false), (b"no newline", true)] {
+            let read = Rc::new(Cell::new(0));
+            let ins = vec![Ok(Input::new(
+                "/dev/urandom",
+                Endless {
+                    pattern,
+                    read: Rc::clone(&read),
+                },
+            ))];
+            let mut out = Vec::<u8>::default();
+            let stats = cat(
+                ins,
+                &mut out,
+                &mut io::sink(),
+                &CatOptions {
+                    max_total_bytes: Some(100),
+                    unique_adjacent,
+                    ..Default::default()
+                },
+            )?;
+
+            assert!(stats.output_limit_reached);
+            assert_eq!(out.len(), 100);
+            assert!(out.starts_with(pattern));
+            // It was read as it was written, not buffered ahead
+            assert!(read.get() <= 2 * MAX_LINE_CHUNK);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn tail_lines_across_inputs() -> io::Result<()> {
+        for (n, expected) in [
+            (0, ""),
+            (2, "     4\tfour\n     5\tfive"),
+            (
+                5,
+                "     1\tone\n     2\ttwo\n     3\tthree\n     4\tfour\n     5\tfive",
+            ),
+            (
+                9,
+                "     1\tone\n     2\ttwo\n     3\tthree\n     4\tfour\n     5\tfive",
+            ),
+        ] {
+            let ins = vec![
+                named("first.txt", "one\ntwo\nthree\n"),
+                named("second.txt", "four\nfive"),
+            ];
+            let mut out = Vec::<u8>::default();
+            let stats = cat(
+                ins,
+                &mut out,
+                &mut io::sink(),
+                &CatOptions {
+                    line_numbers: true,
+                    tail_lines: Some(n),
+                    ..Default::default()
+                },
+            )?;
+
+            assert_eq!(str::from_utf8(&out).unwrap(), expected);
+            assert_eq!(stats.lines, 5);
+        }
+
+        // Joined lines have no ends left to count by
+        let options = CatOptions {
+            tail_lines: Some(1),
+            newline_replacement: Some(b", ".to_vec()),
+            ..Default::default()
+        };
+        let ins = vec![named("first.txt", "l1\nl2\nl3\n")];
+        let mut out = Vec::<u8>::default();
+        let error = cat(ins, &mut out, &mut io::sink(), &options).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(out.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn tail_lines_within_max_total_bytes() -> io::Result<()> {
+        use crate::{Concatenator, InputSource};
+
+        let catter = |bytes| {
+            Concatenator::new(vec![InputSource::Bytes(b"one\ntwo\nthree\n".to_vec())])
+                .with_tail_lines(2)
+                .with_max_total_bytes(bytes)
+                .with_quiet()
+        };
+        // The tail is what the limit is counted against
+        let mut out = Vec::<u8>::default();
+        let stats = catter(6).concatenate_into(&mut out)?;
+        assert_eq!(out, b"two\nth");
+        assert!(stats.output_limit_reached);
+
+        let mut out = Vec::<u8>::default();
+        let stats = catter(10).concatenate_into(&mut out)?;
+        assert_eq!(out, b"two\nthree\n");
+        assert!(!stats.output_limit_reached);
+        Ok(())
+    }
+
+    #[test]
+    fn ended_with_newline_follows_last_input() -> io::Result<()> {
+        for (last, ended_with_newline) in [("last\n", true), ("last", false), ("", true)] {
+            let ins = vec![named("first.txt", "first\n"), named("last.txt", last)];
+            let stats = cat(
+                ins,
+                &mut io::sink(),
+                &mut io::sink(),
+                &CatOptions::default(),
+            )?;
+            assert_eq!(stats.ended_with_newline, ended_with_newline);
+        }
+
+        let stats = cat::<Cursor<&str>, _, _>(
+            vec![],
+            &mut io::sink(),
+            &mut io::sink(),
+            &CatOptions::default(),
+        )?;
+        assert!(!stats.ended_with_newline);
+        Ok(())
+    }
+
+    #[test]
+    fn cat_bytes_numbers_slices() -> io::Result<()> {
+        use crate::cat_bytes;
+
+        let options = CatOptions {
+            line_numbers: true,
+            ..Default::default()
+        };
+        let out = cat_bytes(&[b"one\ntwo\n", b"", b"three"], &options)?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "     1\tone\n     2\ttwo\n     3\tthree"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn cat_reader_matches_cat() -> io::Result<()> {
+        use crate::CatReader;
+        use std::io::Read;
+
+        let options = || CatOptions {
+            line_numbers: true,
+            line_ends: true,
+            tabstop: Some(4),
+            ..Default::default()
+        };
+        let input = [INPUT_STREAM_2, "\tlast line"].concat();
+
+        let mut expected = Vec::<u8>::default();
+        let ins = vec![Ok(Input::stdin(Cursor::new(input.clone())))];
+        cat(ins, &mut expected, &mut io::sink(), &options())?;
+
+        let mut reader = CatReader::new(Cursor::new(input), options())?;
+        let mut out = Vec::<u8>::default();
+        reader.read_to_end(&mut out)?;
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            str::from_utf8(&expected).unwrap()
+        );
+        assert_eq!(reader.stats().map(|stats| stats.lines), Some(4));
+
+        // Warnings go wherever the reader is told to send them
+        let options = CatOptions {
+            summary: true,
+            ..Default::default()
+        };
+        let mut warnings = Vec::<u8>::default();
+        let mut reader =
+            CatReader::new(Cursor::new("one\n"), options)?.with_warnings(&mut warnings);
+        let mut out = String::default();
+        reader.read_to_string(&mut out)?;
+        assert_eq!(out, "one\n");
+        assert_eq!(
+            str::from_utf8(&warnings).unwrap(),
+            "cat: 1 file, 1 line, 4 bytes, 0 errors\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn concatenator_reader_copies_lazily() -> io::Result<()> {
+        use crate::{Concatenator, ConcatenatorReader};
+        use std::{cell::RefCell, rc::Rc};
+
+        // Hooks are called as the reader is read, so they needn't be `Send`
+        let started = Rc::new(RefCell::new(Vec::new()));
+        let started_hook = Rc::clone(&started);
+        let mut reader = ConcatenatorReader::new(
+            Concatenator::default()
+                .add_reader(Cursor::new("one\n"))
+                .add_reader(Cursor::new("two\n"))
+                .with_line_numbers()
+                .on_input_start(move |desc| started_hook.borrow_mut().push(desc.index)),
+        )?;
+        assert!(started.borrow().is_empty());
+        let mut out = Vec::<u8>::default();
+        io::copy(&mut reader, &mut out)?;
+
+        assert_eq!(str::from_utf8(&out).unwrap(), "     1\tone\n     2\ttwo\n");
+        assert_eq!(reader.stats().map(|stats| stats.files), Some(2));
+        assert_eq!(*started.borrow(), [0, 1]);
+
+        // A failed input is an error once the rest has been read
+        let mut reader = ConcatenatorReader::new(
+            Concatenator::default()
+                .add_reader(Cursor::new("one\n"))
+                .add_file("/nonexistent/missing.txt")
+                .with_quiet(),
+        )?;
+        let mut out = Vec::<u8>::default();
+        let error = io::copy(&mut reader, &mut out).unwrap_err();
+        assert_eq!(out, b"one\n");
+        assert!(error.to_string().starts_with("/nonexistent/missing.txt: "));
+
+        // Compression and the checksum come out as they'd be written
+        #[cfg(all(feature = "flate2", feature = "checksum"))]
+        {
+            use crate::ChecksumAlgo;
+
+            let catter = || {
+                Concatenator::default()
+                    .add_reader(Cursor::new("one\n".repeat(10_000)))
+                    .with_gzip_output()
+                    .with_checksum(ChecksumAlgo::Crc32)
+            };
+            let mut expected = Vec::<u8>::default();
+            let written = catter().concatenate_into(&mut expected)?;
+            let mut reader = ConcatenatorReader::new(catter())?;
+            let mut out = Vec::<u8>::default();
+            io::copy(&mut reader, &mut out)?;
+            assert_eq!(out, expected);
+            assert_eq!(
+                reader.stats().and_then(|stats| stats.checksum.as_ref()),
+                written.checksum.as_ref()
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn interleave_two_inputs() -> io::Result<()> {
+        use crate::Concatenator;
+
+        for (first, second, expected) in [
+            ("a1\na2\n", "b1\nb2\n", "a1\nb1\na2\nb2\n"),
+            ("a1\na2\na3\na4", "b1", "a1\nb1\na2\na3\na4"),
+            ("a1", "b1\nb2\n", "a1\nb1\nb2\n"),
+        ] {
+            let mut out = Vec::<u8>::default();
+            Concatenator::default()
+                .add_reader(Cursor::new(first))
+                .add_reader(Cursor::new(second))
+                .with_interleave()
+                .concatenate_into(&mut out)?;
+            assert_eq!(str::from_utf8(&out).unwrap(), expected);
+        }
+
+        let e = Concatenator::default()
+            .add_reader(Cursor::new("a1"))
+            .with_interleave()
+            .concatenate_into(&mut Vec::<u8>::default())
+            .unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        Ok(())
+    }
+
+    #[test]
+    fn unique_adjacent_across_inputs() -> io::Result<()> {
+        let ins = vec![
+            named("first.txt", "a\na\nb\nc\n"),
+            named("second.txt", "c\nc\nd\nb\nb"),
+        ];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                unique_adjacent: true,
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "     1\ta\n     2\tb\n     3\tc\n     4\td\n     5\tb\n     6\tb"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn blank_edges_trimmed() -> io::Result<()> {
+        for (leading, trailing, expected) in [
+            (
+                true,
+                false,
+                "     1\tfirst\n     2\t\n     3\tsecond\n     4\t\n     5\t\r\n",
+            ),
+            (
+                false,
+                true,
+                "     1\t\n     2\t\n     3\tfirst\n     4\t\n     5\tsecond\n",
             ),
-            format!(
-                "{PRE_LINE_NUM_INDENT}4{POST_LINE_NUM_INDENT}{}\n",
-                lines_2[1]
+            (true, true, "     1\tfirst\n     2\t\n     3\tsecond\n"),
+        ] {
+            let ins = vec![
+                named("first.txt", "\n\nfirst\n\n"),
+                named("second.txt", "second\n\n\r\n"),
+            ];
+            let mut out = Vec::<u8>::default();
+            cat(
+                ins,
+                &mut out,
+                &mut io::sink(),
+                &CatOptions {
+                    line_numbers: true,
+                    trim_leading_blanks: leading,
+                    trim_trailing_blanks: trailing,
+                    ..Default::default()
+                },
+            )?;
+            assert_eq!(str::from_utf8(&out).unwrap(), expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn nul_separated_records_numbered() -> io::Result<()> {
+        for (line_ends, expected) in [
+            (false, &b"     1\ta\n2\0     2\tb\0     3\t\0     4\tc"[..]),
+            (true, b"     1\ta\n2$\0     2\tb$\0     3\t$\0     4\tc"),
+        ] {
+            let ins = vec![named("first", &b"a\n2\0b\0"[..]), named("second", b"\0c")];
+            let mut out = Vec::<u8>::default();
+            cat(
+                ins,
+                &mut out,
+                &mut io::sink(),
+                &CatOptions {
+                    line_numbers: true,
+                    line_ends,
+                    record_separator: b'\0',
+                    ..Default::default()
+                },
+            )?;
+
+            assert_eq!(out, expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn newlines_replaced() -> io::Result<()> {
+        for (separator, line_ends, trailing, expected) in [
+            (&b", "[..], false, false, &b"a, b, c"[..]),
+            (b", ", true, false, b"a$, b$, c"),
+            (b", ", false, true, b"a, b, c\n"),
+            (b"\0", false, false, b"a\0b\0c"),
+        ] {
+            let ins = vec![named("first.txt", "a\nb\n"), named("second.txt", "c")];
+            let mut out = Vec::<u8>::default();
+            cat(
+                ins,
+                &mut out,
+                &mut io::sink(),
+                &CatOptions {
+                    newline_replacement: Some(separator.to_vec()),
+                    line_ends,
+                    ensure_trailing_newline: trailing,
+                    ..Default::default()
+                },
+            )?;
+
+            assert_eq!(out, expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn trailing_newline_ensured() -> io::Result<()> {
+        for (ins, expected) in [
+            (vec!["first\n", "last"], "first\nlast\n"),
+            (vec!["first\n", "last\n"], "first\nlast\n"),
+            (vec!["", ""], ""),
+        ] {
+            let ins = ins.into_iter().map(|s| named("in.txt", s)).collect();
+            let mut out = Vec::<u8>::default();
+            cat(
+                ins,
+                &mut out,
+                &mut io::sink(),
+                &CatOptions {
+                    ensure_trailing_newline: true,
+                    ..Default::default()
+                },
+            )?;
+            assert_eq!(str::from_utf8(&out).unwrap(), expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn dry_run_lists_inputs() -> io::Result<()> {
+        let ins = vec![
+            named("exists.txt", INPUT_STREAM_1),
+            Err(Box::from("missing.txt: No such file or directory")),
+            Ok(Input::stdin(Cursor::new(INPUT_STREAM_2))),
+        ];
+        let mut out = Vec::<u8>::default();
+        let stats = list_inputs(ins, &mut out, &CatOptions::default())?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "exists.txt\ncat: missing.txt: No such file or directory\nstandard input\n"
+        );
+        assert_eq!(stats.files, 2);
+        assert_eq!(stats.errors.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn grep_count_per_input() -> io::Result<()> {
+        use super::count_matches;
+        use crate::LinePattern;
+
+        let ins = vec![
+            named("first.txt", INPUT_STREAM_1),
+            Err(Box::from("missing.txt: No such file or directory")),
+            named("second.txt", INPUT_STREAM_2),
+            named("empty.txt", ""),
+        ];
+        let pattern = LinePattern::Substring("file".to_string());
+        let mut out = Vec::<u8>::default();
+        let stats = count_matches(ins, &mut out, &pattern, &CatOptions::default())?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "first.txt: 2\ncat: missing.txt: No such file or directory\nsecond.txt: 2\nempty.txt: 0\n"
+        );
+        assert_eq!(stats.files, 3);
+        assert_eq!(stats.lines, 6);
+
+        // Records end where the separator says
+        let ins = vec![named("z.txt", "x\0a\0a\0")];
+        let pattern = LinePattern::Substring("a".to_string());
+        let options = CatOptions {
+            record_separator: b'\0',
+            ..CatOptions::default()
+        };
+        let mut out = Vec::<u8>::default();
+        let stats = count_matches(ins, &mut out, &pattern, &options)?;
+        assert_eq!(str::from_utf8(&out).unwrap(), "z.txt: 2\n");
+        assert_eq!(stats.lines, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn any_output_buffer_capacity() -> io::Result<()> {
+        use crate::Concatenator;
+
+        for capacity in [1, 1024 * 1024] {
+            let catter = Concatenator::default()
+                .add_reader(Cursor::new(INPUT_STREAM_1))
+                .add_reader(Cursor::new(INPUT_STREAM_2))
+                .with_line_numbers()
+                .with_output_buffer_capacity(capacity);
+            let mut out = Vec::<u8>::default();
+            let writer = catter.buffered(&mut out);
+            assert_eq!(writer.capacity(), capacity);
+            catter.concatenate_to(writer)?;
+
+            let mut expected = Vec::<u8>::default();
+            let ins = vec![
+                Ok(Input::stdin(Cursor::new(INPUT_STREAM_1))),
+                Ok(Input::stdin(Cursor::new(INPUT_STREAM_2))),
+            ];
+            let options = CatOptions {
+                line_numbers: true,
+                ..Default::default()
+            };
+            cat(ins, &mut expected, &mut io::sink(), &options)?;
+            assert_eq!(out, expected);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "tar")]
+    #[test]
+    fn tar_members_catted() -> io::Result<()> {
+        use crate::{Concatenator, InputSource};
+        use std::fs;
+
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, content) in [("b.txt", "second\n"), ("dir/a.txt", INPUT_STREAM_1)] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, content.as_bytes())?;
+        }
+        let archive = builder.into_inner()?;
+
+        let dir = TempDir::new("tar")?;
+        let path = dir.join("archive.tar").to_string_lossy().into_owned();
+        fs::write(&path, archive)?;
+
+        let mut out = Vec::<u8>::default();
+        let stats = Concatenator::new(vec![
+            InputSource::from_name(format!("{path}::dir/a.txt")),
+            InputSource::from_name(format!("{path}::missing.txt")),
+        ])
+        .concatenate_into(&mut out)?;
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            format!("{INPUT_STREAM_1}cat: {path}::missing.txt: No such file or directory\n")
+        );
+        assert_eq!(stats.errors.len(), 1);
+
+        let mut out = Vec::<u8>::default();
+        Concatenator::new(InputSource::tar_members(&path)?).concatenate_into(&mut out)?;
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            format!("second\n{INPUT_STREAM_1}")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn c_escaped_content() -> io::Result<()> {
+        use crate::{OutputTransform, WrapMode};
+
+        let input = b"say \"hi\"\tC:\\dir\n\xe9t\xc3\xa9\x07\r\n";
+        let mut options = CatOptions {
+            transform: OutputTransform::CEscape,
+            ..Default::default()
+        };
+        let mut out = Vec::<u8>::default();
+        let ins = vec![Ok(Input::stdin(Cursor::new(input)))];
+        cat(ins, &mut out, &mut io::sink(), &options)?;
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            r#"say \"hi\"\tC:\\dir\n\351t\303\251\007\r\n"#
+        );
+
+        // A hex digit after an escaped byte stays a character of its own
+        let mut out = Vec::<u8>::default();
+        let ins = vec![Ok(Input::stdin(Cursor::new(b"\xe9a\x011")))];
+        cat(ins, &mut out, &mut io::sink(), &options)?;
+        assert_eq!(str::from_utf8(&out).unwrap(), r"\351a\0011");
+
+        // Folding keeps the lines apart
+        options.max_line_width = Some((8, WrapMode::Wrap));
+        let mut out = Vec::<u8>::default();
+        let ins = vec![Ok(Input::stdin(Cursor::new("0123456789\nab\n")))];
+        cat(ins, &mut out, &mut io::sink(), &options)?;
+        assert_eq!(str::from_utf8(&out).unwrap(), "01234567\\n\n89\\n\nab\\n\n");
+        Ok(())
+    }
+
+    #[test]
+    fn very_long_line() -> io::Result<()> {
+        let content = "0123456789".repeat(1024 * 1024);
+        let ins = vec![
+            named("long.txt", format!("{content}\n")),
+            named("long-unterminated.txt", content.clone()),
+        ];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                ..Default::default()
+            },
+        )?;
+
+        let expected = format!(
+            "{PRE_LINE_NUM_INDENT}1{POST_LINE_NUM_INDENT}{content}\n\
+             {PRE_LINE_NUM_INDENT}2{POST_LINE_NUM_INDENT}{content}"
+        );
+        assert!(out == expected.as_bytes());
+        Ok(())
+    }
+
+    #[test]
+    fn inputs_added_incrementally() -> io::Result<()> {
+        use crate::{Concatenator, InputSource};
+
+        let added = Concatenator::new(vec![])
+            .add_file("first.txt")
+            .add_stdin()
+            .add_file(std::path::PathBuf::from("third.txt"));
+        let listed = Concatenator::new(vec![
+            InputSource::File("first.txt".to_string()),
+            InputSource::StdIn,
+            InputSource::File("third.txt".to_string()),
+        ]);
+        assert_eq!(added.inputs, listed.inputs);
+
+        let mut out = Vec::<u8>::default();
+        Concatenator::new(vec![])
+            .add_reader(Cursor::new(INPUT_STREAM_1))
+            .add_reader(Cursor::new(INPUT_STREAM_2))
+            .add_reader(Cursor::new(INPUT_STREAM_3))
+            .concatenate_into(&mut out)?;
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            format!("{INPUT_STREAM_1}{INPUT_STREAM_2}{INPUT_STREAM_3}")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn options_given_whole() -> io::Result<()> {
+        use crate::Concatenator;
+
+        let mut built = Vec::<u8>::default();
+        Concatenator::default()
+            .add_reader(Cursor::new(INPUT_STREAM_1))
+            .with_line_numbers()
+            .with_line_endings()
+            .concatenate_into(&mut built)?;
+
+        let mut given = Vec::<u8>::default();
+        Concatenator::default()
+            .add_reader(Cursor::new(INPUT_STREAM_1))
+            .with_options(CatOptions {
+                line_numbers: true,
+                line_ends: true,
+                ..Default::default()
+            })
+            .concatenate_into(&mut given)?;
+
+        assert_eq!(built, given);
+        Ok(())
+    }
+
+    #[test]
+    fn default_and_from_paths_constructors() -> io::Result<()> {
+        use crate::Concatenator;
+        use std::fs;
+
+        let mut out = Vec::<u8>::default();
+        Concatenator::default().concatenate_into(&mut out)?;
+        assert!(out.is_empty());
+
+        let dir = TempDir::new("from-paths")?;
+        let paths = [INPUT_STREAM_1, INPUT_STREAM_2]
+            .iter()
+            .enumerate()
+            .map(|(i, content)| {
+                let path = dir.join(format!("{i}.txt"));
+                fs::write(&path, content).map(|_| path)
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        Concatenator::from_paths(&paths)
+            .with_line_numbers()
+            .concatenate_into(&mut out)?;
+
+        let mut expected = Vec::<u8>::default();
+        cat(
+            vec![
+                named("0.txt", INPUT_STREAM_1),
+                named("1.txt", INPUT_STREAM_2),
+            ],
+            &mut expected,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(out, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn word_wrap_breaks_at_blanks() -> io::Result<()> {
+        use crate::WrapMode::{Word, Wrap};
+
+        // Both match what `fold -w 10` and `fold -s -w 10` write
+
+        let sentence = "The quick brown fox jumps over extraordinarily lazy dogs\n";
+        for (mode, expected) in [
+            (
+                Wrap,
+                "The quick \nbrown fox \njumps over\n extraordi\nnarily laz\ny dogs\n",
             ),
-            format!(
-                "{PRE_LINE_NUM_INDENT}5{POST_LINE_NUM_INDENT}{}\n",
-                lines_2[2]
+            (
+                Word,
+                "The quick \nbrown fox \njumps \nover \nextraordin\narily \nlazy dogs\n",
             ),
+        ] {
+            let mut out = Vec::<u8>::default();
+            cat(
+                vec![named("sentence.txt", sentence)],
+                &mut out,
+                &mut io::sink(),
+                &CatOptions {
+                    max_line_width: Some((10, mode)),
+                    ..Default::default()
+                },
+            )?;
+            assert_eq!(str::from_utf8(&out).unwrap(), expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn line_prefix_and_suffix() -> io::Result<()> {
+        // The long line is read in two pieces, but is still one line
+        let long = "x".repeat(64 * 1024 + 10);
+        let ins = vec![
+            named("first.txt", format!("quoted\n\n{long}\n")),
+            Err(Box::from(ERROR_1)),
+            named("second.txt", "last".to_string()),
+        ];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_numbers: true,
+                line_ends: true,
+                line_prefix: Some("> ".to_string()),
+                line_suffix: Some(" <".to_string()),
+                ..Default::default()
+            },
+        )?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
             format!(
-                "{PRE_LINE_NUM_INDENT}6{POST_LINE_NUM_INDENT}{}\n",
-                lines_3[0]
-            ),
+                "     1\t> quoted <$\n     2\t>  <$\n     3\t> {long} <$\n\
+                 cat: {ERROR_1}\n     4\t> last <"
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn files_over_max_size_skipped() -> io::Result<()> {
+        use super::{open_input, InputSource};
+        use std::fs;
+
+        let dir = TempDir::new("max-size")?;
+        let small = dir.join("small").to_string_lossy().into_owned();
+        let large = dir.join("large").to_string_lossy().into_owned();
+        fs::write(&small, "0123456789")?;
+        fs::write(&large, "0123456789a")?;
+
+        let options = CatOptions {
+            max_file_size: Some(10),
+            ..Default::default()
+        };
+        let mut err = Vec::<u8>::default();
+        let small_input = open_input(InputSource::File(small), &options, &mut err)?;
+        let large_input = open_input(InputSource::File(large.clone()), &options, &mut err)?;
+        let stdin_input = open_input(InputSource::StdIn, &options, &mut err)?;
+
+        assert!(small_input.is_some_and(|input| input.is_ok()));
+        assert!(large_input.is_none());
+        assert!(stdin_input.is_some());
+        assert_eq!(
+            str::from_utf8(&err).unwrap(),
+            format!("cat: {large}: file too large (11 bytes)\n")
+        );
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn validate_checks_files_without_reading() -> io::Result<()> {
+        use crate::{Concatenator, InputSource};
+        use std::fs;
+
+        let dir = TempDir::new("validate")?;
+        let exists = dir.join("exists.txt");
+        fs::write(&exists, "here\n")?;
+        let missing = dir.join("missing.txt");
+
+        let catter = Concatenator::from_paths([&exists, &missing, &dir.to_path_buf()]);
+        let mut results = catter.validate();
+        results.extend(Concatenator::new(vec![InputSource::StdIn]).validate());
+
+        assert_eq!(results.len(), 4);
+        assert!(results[0].is_ok());
+        let missing_error = results[1].as_ref().unwrap_err();
+        assert_eq!(missing_error.input, 1);
+        assert!(missing_error
+            .message
+            .starts_with(&format!("{}: No such file", missing.display())));
+        let dir_error = results[2].as_ref().unwrap_err();
+        assert_eq!(dir_error.input, 2);
+        assert_eq!(
+            dir_error.message,
+            format!("{}: Is a directory", dir.display())
+        );
+        assert!(results[3].is_ok());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dir_files_walked_in_order() -> io::Result<()> {
+        use crate::Concatenator;
+        use std::{fs, os::unix::fs::symlink};
+
+        let dir = TempDir::new("recursive")?;
+        fs::create_dir_all(dir.join("b/inner"))?;
+        fs::create_dir_all(dir.join("a"))?;
+        fs::write(dir.join("c.txt"), "c\n")?;
+        fs::write(dir.join("b/inner/x.txt"), "b/inner/x\n")?;
+        fs::write(dir.join("b/a.txt"), "b/a\n")?;
+        fs::write(dir.join("a/z.txt"), "a/z\n")?;
+        symlink(dir.join("c.txt"), dir.join("link.txt"))?;
+        symlink(dir.join("b"), dir.join("link"))?;
+
+        let mut out = Vec::<u8>::default();
+        let stats = Concatenator::default()
+            .add_dir_recursive(&dir)?
+            .concatenate_into(&mut out)?;
+
+        // Without recursing, a directory is an error like any other bad
+        // input, and the inputs after it are still catted
+        let file = dir.join("c.txt");
+        let mut not_recursive_out = Vec::<u8>::default();
+        let not_recursive = Concatenator::from_paths([&file, &dir.to_path_buf(), &file])
+            .with_quiet()
+            .concatenate_into(&mut not_recursive_out)?;
+
+        assert_eq!(str::from_utf8(&out).unwrap(), "a/z\nb/a\nb/inner/x\nc\n");
+        assert_eq!(stats.files, 4);
+        assert_eq!(str::from_utf8(&not_recursive_out).unwrap(), "c\nc\n");
+        assert_eq!(not_recursive.errors.len(), 1);
+        let error = &not_recursive.errors[0];
+        assert_eq!(error.input, 1);
+        assert!(error.message.starts_with(&format!("{}: ", dir.display())));
+        assert!(error.message.contains("Is a directory"));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn missing_files_ignored() -> io::Result<()> {
+        use crate::Concatenator;
+        use std::fs;
+
+        let dir = TempDir::new("ignore-missing")?;
+        let exists = dir.join("exists.txt");
+        fs::write(&exists, "here\n")?;
+        let missing = dir.join("missing.txt");
+        // Not a directory, rather than not found
+        let not_dir = exists.join("child.txt");
+
+        let mut out = Vec::<u8>::default();
+        let stats = Concatenator::from_paths([&missing, &exists, &not_dir])
+            .with_ignore_missing()
+            .concatenate_into(&mut out)?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
             format!(
-                "{PRE_LINE_NUM_INDENT}7{POST_LINE_NUM_INDENT}{}\n",
-                lines_3[1]
-            ),
-            format!("{PRE_LINE_NUM_INDENT}8{POST_LINE_NUM_INDENT}{}", lines_3[2]),
+                "here\ncat: {}: Not a directory (os error 20)\n",
+                not_dir.display()
+            )
+        );
+        assert_eq!(stats.files, 1);
+        assert_eq!(stats.errors.len(), 1);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn fd_input_read_from_pipe() -> io::Result<()> {
+        use crate::{Concatenator, InputSource};
+        use std::{fs, io::Write, os::fd::FromRawFd};
+
+        let mut fds = [0; 2];
+        // SAFETY: `fds` has room for the two descriptors `pipe` makes
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let [read_end, write_end] = fds;
+        // SAFETY: the write end was just opened, and nothing else owns it
+        let mut writer = unsafe { fs::File::from_raw_fd(write_end) };
+        writer.write_all(b"through\na pipe\n")?;
+        drop(writer);
+
+        let mut out = Vec::<u8>::default();
+        let stats = Concatenator::new(vec![InputSource::Fd(read_end)])
+            .with_line_numbers()
+            .concatenate_into(&mut out)?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "     1\tthrough\n     2\ta pipe\n"
+        );
+        assert_eq!(stats.files, 1);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn fifo_inputs_warned_or_skipped() -> io::Result<()> {
+        use super::open_fifo;
+        use std::{ffi::CString, fs, io::Read, thread, time::Duration};
+
+        let dir = TempDir::new("fifo")?;
+        let path = dir.join("fifo").to_string_lossy().into_owned();
+        let c_path = CString::new(path.clone()).unwrap();
+        // SAFETY: `c_path` is a valid NUL-terminated path
+        assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
+
+        // Line counting for auto width leaves it alone rather than waiting
+        let fifo_input = [crate::InputSource::File(path.clone())];
+        assert_eq!(
+            super::count_lines(&fifo_input, &CatOptions::default()),
+            None
+        );
+
+        // Nothing is writing to it, so it's skipped without blocking
+        let mut err = Vec::<u8>::default();
+        let options = CatOptions {
+            no_block: true,
+            ..Default::default()
+        };
+        assert!(open_fifo(&path, &options, &mut err)?.is_none());
+        assert_eq!(
+            str::from_utf8(&err).unwrap(),
+            format!("cat: {path}: is a FIFO with nothing to read, skipping\n")
+        );
+
+        // Otherwise it waits for the writer, after a warning
+        let writer_path = path.clone();
+        let writer = thread::spawn(move || fs::write(writer_path, "through the pipe\n"));
+        let mut err = Vec::<u8>::default();
+        let input = open_fifo(&path, &CatOptions::default(), &mut err)?;
+        let mut content = String::new();
+        input
+            .unwrap()
+            .unwrap()
+            .reader
+            .read_to_string(&mut content)?;
+        writer.join().unwrap()?;
+
+        // With a timeout, waiting for a writer that never comes gives up
+        let options = CatOptions {
+            input_timeout: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let input = open_fifo(&path, &options, &mut io::sink())?;
+        let timed_out = input
+            .unwrap()
+            .unwrap()
+            .reader
+            .read_to_string(&mut String::new())
+            .unwrap_err();
+
+        assert_eq!(content, "through the pipe\n");
+        assert_eq!(
+            str::from_utf8(&err).unwrap(),
+            format!("cat: {path}: is a FIFO, waiting for writer\n")
+        );
+        assert_eq!(timed_out.kind(), io::ErrorKind::TimedOut);
+        Ok(())
+    }
+
+    #[test]
+    fn stdin_read_once() -> io::Result<()> {
+        use crate::InputSource::{File, StdIn};
+
+        let mut err = Vec::<u8>::default();
+        let inputs = drop_reused_stdin(
+            vec![StdIn, File("a.txt".to_string()), StdIn, StdIn],
+            &mut err,
+            &CatOptions::default(),
+        )?;
+
+        assert_eq!(inputs, [StdIn, File("a.txt".to_string())]);
+        assert_eq!(
+            str::from_utf8(&err).unwrap(),
+            "cat: standard input reused\ncat: standard input reused\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn summary_on_err() -> io::Result<()> {
+        let ins = vec![
+            named("first.txt", INPUT_STREAM_1),
+            named("second.txt", INPUT_STREAM_2),
+            Err(Box::from(ERROR_1)),
+            named("third.txt", INPUT_STREAM_3),
         ];
+        let mut out = Vec::<u8>::default();
+        let mut err = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut err,
+            &CatOptions {
+                summary: true,
+                ..Default::default()
+            },
+        )?;
 
-        assert_eq!(str::from_utf8(&out).unwrap(), expected_out.join(""));
+        // The first line of the second file carries on the last one of the
+        // first, and the error line isn't read from any input
+        assert_eq!(
+            str::from_utf8(&err).unwrap(),
+            "cat: 3 files, 8 lines, 251 bytes, 1 error\n"
+        );
         Ok(())
     }
 
     #[test]
-    fn line_nums_with_error_correct_out() -> io::Result<()> {
+    fn input_errors_collected() -> io::Result<()> {
         let ins = vec![
-            Ok(Cursor::new(INPUT_STREAM_1)),
-            Err(ERROR_1.into()),
-            Ok(Cursor::new(INPUT_STREAM_2)),
-            Ok(Cursor::new(INPUT_STREAM_3)),
+            named("first.txt", INPUT_STREAM_1),
+            Err(Box::from(ERROR_1)),
+            named("third.txt", INPUT_STREAM_2),
+            Err(Box::from("missing.txt: No such file or directory")),
+            named("fifth.txt", INPUT_STREAM_3),
         ];
         let mut out = Vec::<u8>::default();
-        cat(ins, &mut out, true, false)?;
+        let stats = cat(ins, &mut out, &mut io::sink(), &CatOptions::default())?;
 
-        let (lines_1, lines_2, lines_3) = (
-            INPUT_STREAM_1.lines().collect::<Vec<_>>(),
-            INPUT_STREAM_2.lines().collect::<Vec<_>>(),
-            INPUT_STREAM_3.lines().collect::<Vec<_>>(),
+        let errors = stats
+            .errors
+            .iter()
+            .map(|e| (e.input, e.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            [
+                (1, ERROR_1.to_string()),
+                (3, "missing.txt: No such file or directory".to_string())
+            ]
         );
-
-        let expected_out = vec![
-            format!(
-                "{PRE_LINE_NUM_INDENT}1{POST_LINE_NUM_INDENT}{}\n",
-                lines_1[0]
-            ),
-            format!(
-                "{PRE_LINE_NUM_INDENT}2{POST_LINE_NUM_INDENT}{}\n",
-                lines_1[1]
-            ),
-            format!(
-                "{PRE_LINE_NUM_INDENT}3{POST_LINE_NUM_INDENT}{}cat: {ERROR_1}\n",
-                lines_1[2]
-            ),
-            format!(
-                "{PRE_LINE_NUM_INDENT}4{POST_LINE_NUM_INDENT}{}\n",
-                lines_2[0]
-            ),
-            format!(
-                "{PRE_LINE_NUM_INDENT}5{POST_LINE_NUM_INDENT}{}\n",
-                lines_2[1]
-            ),
-            format!(
-                "{PRE_LINE_NUM_INDENT}6{POST_LINE_NUM_INDENT}{}\n",
-                lines_2[2]
-            ),
-            format!(
-                "{PRE_LINE_NUM_INDENT}7{POST_LINE_NUM_INDENT}{}\n",
-                lines_3[0]
-            ),
-            format!(
-                "{PRE_LINE_NUM_INDENT}8{POST_LINE_NUM_INDENT}{}\n",
-                lines_3[1]
-            ),
-            format!("{PRE_LINE_NUM_INDENT}9{POST_LINE_NUM_INDENT}{}", lines_3[2]),
-        ];
-
-        assert_eq!(str::from_utf8(&out).unwrap(), expected_out.join(""));
         Ok(())
     }
 
     #[test]
-    fn line_ends_correct_out() -> io::Result<()> {
+    fn strict_stops_at_first_error() {
+        use crate::Concatenator;
+
+        let mut first = Cursor::new(INPUT_STREAM_1);
+        let mut third = Cursor::new(INPUT_STREAM_3);
         let ins = vec![
-            Ok(Cursor::new(INPUT_STREAM_1)),
-            Ok(Cursor::new(INPUT_STREAM_2)),
-            Ok(Cursor::new(INPUT_STREAM_3)),
+            Ok(Input::new("first.txt", &mut first)),
+            Err(Box::from(ERROR_1)),
+            Ok(Input::new("third.txt", &mut third)),
         ];
         let mut out = Vec::<u8>::default();
-        cat(ins, &mut out, false, true)?;
-
-        let (lines_1, lines_2, lines_3) = (
-            INPUT_STREAM_1.lines().collect::<Vec<_>>(),
-            INPUT_STREAM_2.lines().collect::<Vec<_>>(),
-            INPUT_STREAM_3.lines().collect::<Vec<_>>(),
+        let result = cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                strict: true,
+                ..Default::default()
+            },
         );
 
-        let expected_out = vec![
-            format!("{}$\n", lines_1[0]),
-            format!("{}$\n", lines_1[1]),
-            format!("{}{}$\n", lines_1[2], lines_2[0]),
-            format!("{}$\n", lines_2[1]),
-            format!("{}$\n", lines_2[2]),
-            format!("{}$\n", lines_3[0]),
-            format!("{}$\n", lines_3[1]),
-            format!("{}", lines_3[2]),
-        ];
+        assert_eq!(result.unwrap_err().to_string(), ERROR_1);
+        assert_eq!(str::from_utf8(&out).unwrap(), INPUT_STREAM_1);
+        assert_eq!(third.position(), 0);
 
-        assert_eq!(str::from_utf8(&out).unwrap(), expected_out.join(""));
+        // The error keeps its kind
+        let error = Concatenator::default()
+            .add_file("/nonexistent/missing.txt")
+            .with_strict()
+            .concatenate_into(&mut out)
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert!(error.to_string().starts_with("/nonexistent/missing.txt: "));
+    }
+
+    #[test]
+    fn read_errors_collected_and_skipped() -> io::Result<()> {
+        use super::OpenedInput;
+        use std::io::{BufRead, Read};
+
+        // Opens fine, but fails once read from
+        struct Unreadable;
+
+        impl Read for Unreadable {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("device error"))
+            }
+        }
+
+        impl BufRead for Unreadable {
+            fn fill_buf(&mut self) -> io::Result<&[u8]> {
+                Err(io::Error::other("device error"))
+            }
+
+            fn consume(&mut self, _: usize) {}
+        }
+
+        let ins = || -> Vec<OpenedInput> {
+            vec![
+                Ok(Input::new("first.txt", Box::new(Cursor::new("first\n")))),
+                Ok(Input::new("bad.txt", Box::new(Unreadable))),
+                Ok(Input::new("third.txt", Box::new(Cursor::new("third\n")))),
+            ]
+        };
+        let mut out = Vec::<u8>::default();
+        let stats = cat(ins(), &mut out, &mut io::sink(), &CatOptions::default())?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "first\ncat: bad.txt: device error\nthird\n"
+        );
+        assert_eq!(stats.errors.len(), 1);
+        assert_eq!(stats.errors[0].input, 1);
+        assert_eq!(stats.errors[0].to_string(), "bad.txt: device error");
+
+        // Strict runs still stop at it
+        let mut out = Vec::<u8>::default();
+        let options = CatOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let result = cat(ins(), &mut out, &mut io::sink(), &options);
+        assert_eq!(result.unwrap_err().to_string(), "device error");
+        assert_eq!(out, b"first\n");
         Ok(())
     }
 
     #[test]
-    fn line_ends_with_error_correct_out() -> io::Result<()> {
+    fn mixed_line_endings_warned() -> io::Result<()> {
         let ins = vec![
-            Ok(Cursor::new(INPUT_STREAM_1)),
-            Err(ERROR_1.into()),
-            Ok(Cursor::new(INPUT_STREAM_2)),
-            Ok(Cursor::new(INPUT_STREAM_3)),
+            named("mixed.txt", "unix\nwindows\r\nunix again\n"),
+            named("crlf.txt", "windows\r\nwindows\r\n"),
+            named("lf.txt", "unix\nunix, no newline\r"),
         ];
         let mut out = Vec::<u8>::default();
-        cat(ins, &mut out, false, true)?;
+        let mut err = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut err,
+            &CatOptions {
+                warn_mixed_endings: true,
+                ..Default::default()
+            },
+        )?;
 
-        let (lines_1, lines_2, lines_3) = (
-            INPUT_STREAM_1.lines().collect::<Vec<_>>(),
-            INPUT_STREAM_2.lines().collect::<Vec<_>>(),
-            INPUT_STREAM_3.lines().collect::<Vec<_>>(),
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            "unix\nwindows\r\nunix again\nwindows\r\nwindows\r\nunix\nunix, no newline\r"
+        );
+        assert_eq!(
+            str::from_utf8(&err).unwrap(),
+            "cat: mixed.txt: mixed line endings\n"
         );
+        Ok(())
+    }
 
-        let expected_out = vec![
-            format!("{}$\n", lines_1[0]),
-            format!("{}$\n", lines_1[1]),
-            format!("{}cat: {ERROR_1}\n", lines_1[2]),
-            format!("{}$\n", lines_2[0]),
-            format!("{}$\n", lines_2[1]),
-            format!("{}$\n", lines_2[2]),
-            format!("{}$\n", lines_3[0]),
-            format!("{}$\n", lines_3[1]),
-            format!("{}", lines_3[2]),
-        ];
+    #[test]
+    fn output_line_ending_replaces_newlines() -> io::Result<()> {
+        use crate::LineEnding::{Cr, CrLf};
 
-        assert_eq!(str::from_utf8(&out).unwrap(), expected_out.join(""));
+        for (ending, expected) in [
+            (CrLf, "short$\r\n12345\r\n6789$\r\ncat: oops\r\nlast"),
+            (Cr, "short$\r12345\r6789$\rcat: oops\rlast"),
+        ] {
+            let ins = vec![
+                named("first.txt", "short\n123456789\n"),
+                Err(Box::from("oops")),
+                named("second.txt", "last"),
+            ];
+            let mut out = Vec::<u8>::default();
+            cat(
+                ins,
+                &mut out,
+                &mut io::sink(),
+                &CatOptions {
+                    line_ends: true,
+                    max_line_width: Some((5, crate::WrapMode::Wrap)),
+                    line_ending: ending,
+                    ..Default::default()
+                },
+            )?;
+
+            assert_eq!(str::from_utf8(&out).unwrap(), expected);
+        }
         Ok(())
     }
 
     #[test]
-    fn line_nums_and_ends_correct_out() -> io::Result<()> {
+    fn preserve_dominant_ending_follows_first_input() -> io::Result<()> {
+        for (first, expected) in [
+            ("a\r\nb\r\nc\n", "a\r\nb\r\nc\r\nd\r\ne\r\n"),
+            ("a\nb\nc\r\n", "a\nb\nc\nd\ne\n"),
+        ] {
+            let ins = vec![named("first.txt", first), named("second.txt", "d\ne\r\n")];
+            let mut out = Vec::<u8>::default();
+            cat(
+                ins,
+                &mut out,
+                &mut io::sink(),
+                &CatOptions {
+                    preserve_dominant_ending: true,
+                    ..Default::default()
+                },
+            )?;
+
+            assert_eq!(str::from_utf8(&out).unwrap(), expected);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "syntect")]
+    #[test]
+    fn syntax_highlight_known_extension_colors() -> io::Result<()> {
+        let ins = vec![named("main.rs", "fn main() {}\n")];
+        let mut out = Vec::<u8>::default();
+        cat(
+            ins,
+            &mut out,
+            &mut io::sink(),
+            &CatOptions {
+                line_ends: true,
+                syntax_highlight: true,
+                ..Default::default()
+            },
+        )?;
+
+        let out = str::from_utf8(&out).unwrap();
+        assert!(out.starts_with("\x1b[38;2;"));
+        assert!(out.ends_with("\x1b[0m$\n"));
+        Ok(())
+    }
+
+    #[cfg(feature = "ureq")]
+    #[test]
+    fn url_inputs_fetch_body_and_report_bad_status() -> io::Result<()> {
+        use crate::InputSource;
+        use std::{io::Read, io::Write, net::TcpListener, thread};
+
+        // A tiny server answering one request per connection: /file.txt
+        // gets a fixed body and everything else gets a 404
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let server = thread::spawn(move || {
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let mut request = [0; 1024];
+                let n = stream.read(&mut request).unwrap();
+                let (status, body) = if request[..n].starts_with(b"GET /file.txt ") {
+                    ("200 OK", INPUT_STREAM_1)
+                } else {
+                    ("404 Not Found", "")
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+            }
+        });
+
+        let missing = format!("http://{addr}/missing.txt");
         let ins = vec![
-            Ok(Cursor::new(INPUT_STREAM_1)),
-            Ok(Cursor::new(INPUT_STREAM_2)),
-            Ok(Cursor::new(INPUT_STREAM_3)),
-        ];
+            InputSource::Url(format!("http://{addr}/file.txt")),
+            InputSource::Url(missing.clone()),
+        ]
+        .into_iter()
+        .map(InputSource::get_buf_read)
+        .collect();
         let mut out = Vec::<u8>::default();
-        cat(ins, &mut out, true, true)?;
+        cat(ins, &mut out, &mut io::sink(), &CatOptions::default())?;
+        server.join().unwrap();
 
-        let (lines_1, lines_2, lines_3) = (
-            INPUT_STREAM_1.lines().collect::<Vec<_>>(),
-            INPUT_STREAM_2.lines().collect::<Vec<_>>(),
-            INPUT_STREAM_3.lines().collect::<Vec<_>>(),
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            format!("{INPUT_STREAM_1}cat: {missing}: 404 Not Found\n")
         );
+        Ok(())
+    }
 
-        let expected_out = vec![
-            format!(
-                "{PRE_LINE_NUM_INDENT}1{POST_LINE_NUM_INDENT}{}$\n",
-                lines_1[0]
-            ),
-            format!(
-                "{PRE_LINE_NUM_INDENT}2{POST_LINE_NUM_INDENT}{}$\n",
-                lines_1[1]
-            ),
-            format!(
-                "{PRE_LINE_NUM_INDENT}3{POST_LINE_NUM_INDENT}{}{}$\n",
-                lines_1[2], lines_2[0]
-            ),
-            format!(
-                "{PRE_LINE_NUM_INDENT}4{POST_LINE_NUM_INDENT}{}$\n",
-                lines_2[1]
-            ),
-            format!(
-                "{PRE_LINE_NUM_INDENT}5{POST_LINE_NUM_INDENT}{}$\n",
-                lines_2[2]
-            ),
-            format!(
-                "{PRE_LINE_NUM_INDENT}6{POST_LINE_NUM_INDENT}{}$\n",
-                lines_3[0]
-            ),
-            format!(
-                "{PRE_LINE_NUM_INDENT}7{POST_LINE_NUM_INDENT}{}$\n",
-                lines_3[1]
-            ),
-            format!("{PRE_LINE_NUM_INDENT}8{POST_LINE_NUM_INDENT}{}", lines_3[2]),
-        ];
+    #[cfg(feature = "glob")]
+    #[test]
+    fn from_glob_sorted_matches() -> io::Result<()> {
+        use crate::Concatenator;
+        use std::fs;
 
-        assert_eq!(str::from_utf8(&out).unwrap(), expected_out.join(""));
+        let dir = TempDir::new("glob")?;
+        for (name, content) in [
+            ("b.txt", "b\n"),
+            ("a.txt", "a\n"),
+            ("c.md", "c\n"),
+            ("d.txt", "d\n"),
+        ] {
+            fs::write(dir.join(name), content)?;
+        }
+        let pattern = dir.join("*.txt").to_string_lossy().into_owned();
+        let unmatched = dir.join("*.rs").to_string_lossy().into_owned();
+
+        let mut out = Vec::<u8>::default();
+        Concatenator::from_glob(&pattern)?.concatenate_into(&mut out)?;
+        let error = Concatenator::from_glob(&unmatched).err().unwrap();
+        let invalid = Concatenator::from_glob("[").err().unwrap();
+
+        assert_eq!(str::from_utf8(&out).unwrap(), "a\nb\nd\n");
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert_eq!(error.to_string(), format!("{unmatched}: no files match"));
+        assert_eq!(invalid.kind(), io::ErrorKind::InvalidInput);
         Ok(())
     }
 
+    #[cfg(feature = "zstd")]
     #[test]
-    fn line_nums_and_ends_with_error_correct_out() -> io::Result<()> {
-        let ins = vec![
-            Ok(Cursor::new(INPUT_STREAM_1)),
-            Err(ERROR_1.into()),
-            Ok(Cursor::new(INPUT_STREAM_2)),
-            Ok(Cursor::new(INPUT_STREAM_3)),
-        ];
+    fn zstd_files_decompressed() -> io::Result<()> {
+        use crate::InputSource;
+        use std::fs;
+
+        // One file named for its compression, one found by its magic number
+        let dir = TempDir::new("zstd")?;
+        let named = dir.join("first.txt.zst");
+        let sniffed = dir.join("second.txt");
+        fs::write(&named, zstd::encode_all(INPUT_STREAM_1.as_bytes(), 0)?)?;
+        fs::write(&sniffed, zstd::encode_all(INPUT_STREAM_2.as_bytes(), 0)?)?;
+
+        let ins = [named, sniffed]
+            .map(|path| InputSource::File(path.to_string_lossy().into_owned()))
+            .into_iter()
+            .map(InputSource::get_buf_read)
+            .collect();
         let mut out = Vec::<u8>::default();
-        cat(ins, &mut out, true, true)?;
+        cat(ins, &mut out, &mut io::sink(), &CatOptions::default())?;
 
-        let (lines_1, lines_2, lines_3) = (
-            INPUT_STREAM_1.lines().collect::<Vec<_>>(),
-            INPUT_STREAM_2.lines().collect::<Vec<_>>(),
-            INPUT_STREAM_3.lines().collect::<Vec<_>>(),
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            format!("{INPUT_STREAM_1}{INPUT_STREAM_2}")
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "bzip2")]
+    #[test]
+    fn bzip2_files_decompressed() -> io::Result<()> {
+        use crate::InputSource;
+        use bzip2::{write::BzEncoder, Compression};
+        use std::{fs, io::Write};
+
+        let compress = |content: &str| -> io::Result<Vec<u8>> {
+            let mut encoder = BzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(content.as_bytes())?;
+            encoder.finish()
+        };
+
+        // One file named for its compression, one found by its magic number,
+        // and text that only starts like it
+        let dir = TempDir::new("bzip2")?;
+        let named = dir.join("first.txt.bz2");
+        let sniffed = dir.join("second.txt");
+        let text = dir.join("third.txt");
+        fs::write(&named, compress(INPUT_STREAM_1)?)?;
+        fs::write(&sniffed, compress(INPUT_STREAM_2)?)?;
+        fs::write(&text, "BZh9 is my text\n")?;
+
+        let ins = [named, sniffed, text]
+            .map(|path| InputSource::File(path.to_string_lossy().into_owned()))
+            .into_iter()
+            .map(InputSource::get_buf_read)
+            .collect();
+        let mut out = Vec::<u8>::default();
+        cat(ins, &mut out, &mut io::sink(), &CatOptions::default())?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            format!("{INPUT_STREAM_1}{INPUT_STREAM_2}BZh9 is my text\n")
         );
 
-        let expected_out = vec![
-            format!(
-                "{PRE_LINE_NUM_INDENT}1{POST_LINE_NUM_INDENT}{}$\n",
-                lines_1[0]
-            ),
-            format!(
-                "{PRE_LINE_NUM_INDENT}2{POST_LINE_NUM_INDENT}{}$\n",
-                lines_1[1]
-            ),
-            format!(
-                "{PRE_LINE_NUM_INDENT}3{POST_LINE_NUM_INDENT}{}cat: {ERROR_1}\n",
-                lines_1[2]
-            ),
-            format!(
-                "{PRE_LINE_NUM_INDENT}4{POST_LINE_NUM_INDENT}{}$\n",
-                lines_2[0]
-            ),
-            format!(
-                "{PRE_LINE_NUM_INDENT}5{POST_LINE_NUM_INDENT}{}$\n",
-                lines_2[1]
-            ),
-            format!(
-                "{PRE_LINE_NUM_INDENT}6{POST_LINE_NUM_INDENT}{}$\n",
-                lines_2[2]
-            ),
-            format!(
-                "{PRE_LINE_NUM_INDENT}7{POST_LINE_NUM_INDENT}{}$\n",
-                lines_3[0]
-            ),
-            format!(
-                "{PRE_LINE_NUM_INDENT}8{POST_LINE_NUM_INDENT}{}$\n",
-                lines_3[1]
-            ),
-            format!("{PRE_LINE_NUM_INDENT}9{POST_LINE_NUM_INDENT}{}", lines_3[2]),
-        ];
+        // What comes out of zstd is left as it is, even if it's bzip2
+        #[cfg(feature = "zstd")]
+        {
+            let nested = dir.join("nested.txt");
+            let bzipped = compress(INPUT_STREAM_1)?;
+            fs::write(&nested, zstd::encode_all(bzipped.as_slice(), 0)?)?;
+            let ins = vec![InputSource::File(nested.to_string_lossy().into_owned()).get_buf_read()];
+            let mut out = Vec::<u8>::default();
+            cat(ins, &mut out, &mut io::sink(), &CatOptions::default())?;
+            assert_eq!(out, bzipped);
+        }
+        Ok(())
+    }
 
-        assert_eq!(str::from_utf8(&out).unwrap(), expected_out.join(""));
+    #[cfg(feature = "xz2")]
+    #[test]
+    fn xz_files_decompressed() -> io::Result<()> {
+        use crate::InputSource;
+        use std::{fs, io::Write};
+        use xz2::write::XzEncoder;
+
+        let compress = |content: &str| -> io::Result<Vec<u8>> {
+            let mut encoder = XzEncoder::new(Vec::new(), 6);
+            encoder.write_all(content.as_bytes())?;
+            encoder.finish()
+        };
+
+        // One file named for its compression, one found by its magic number
+        let dir = TempDir::new("xz")?;
+        let named = dir.join("first.txt.xz");
+        let sniffed = dir.join("second.txt");
+        fs::write(&named, compress(INPUT_STREAM_1)?)?;
+        fs::write(&sniffed, compress(INPUT_STREAM_2)?)?;
+
+        let ins = [named, sniffed]
+            .map(|path| InputSource::File(path.to_string_lossy().into_owned()))
+            .into_iter()
+            .map(InputSource::get_buf_read)
+            .collect();
+        let mut out = Vec::<u8>::default();
+        cat(ins, &mut out, &mut io::sink(), &CatOptions::default())?;
+
+        assert_eq!(
+            str::from_utf8(&out).unwrap(),
+            format!("{INPUT_STREAM_1}{INPUT_STREAM_2}")
+        );
         Ok(())
     }
 }
@@ -1,5 +1,17 @@
+#[cfg(feature = "checksum")]
+use cat::ChecksumAlgo;
+use cat::{
+    CapMode, CatStats, Concatenator, Encoding, InputSource, LineEnding, LinePattern, NumberFormat,
+    NumberStyle, WrapMode,
+};
 use clap::Parser;
-use cat::{Concatenator, InputSource};
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+    process,
+    time::Duration,
+};
 
 const ABOUT: &str = r"
 Concatenate FILE(s) to standard output.
@@ -12,22 +24,378 @@ With no FILE, or when FILE is -, read standard input.";
 struct Args {
     file: Vec<String>,
 
+    /// read the names of the input files from the NUL-separated list in F;
+    /// if F is - then read names from standard input
+    #[arg(long = "files0-from", value_name = "F", conflicts_with = "file")]
+    files0_from: Option<String>,
+
     /// number all output lines
     #[arg(short = 'n', long = "number")]
     show_line_numbers: bool,
 
+    /// prefix each line with the byte offset it starts at, instead of a number
+    #[arg(long = "byte-offsets", conflicts_with = "show_line_numbers")]
+    byte_offsets: bool,
+
+    /// prefix each line with the file it came from and the byte offset it
+    /// starts at within that file
+    #[arg(long = "source-offsets")]
+    source_offsets: bool,
+
+    /// prefix each line with the time it's written at, in UTC, formatted
+    /// like strftime would with %Y, %m, %d, %H, %M, %S and %s
+    #[arg(long = "timestamp", value_name = "FORMAT")]
+    timestamp: Option<String>,
+
+    /// radix of line numbers
+    #[arg(long = "number-format", value_name = "FORMAT", value_parser = ["dec", "hex", "oct"])]
+    number_format: Option<String>,
+
+    /// mark hex and octal line numbers with 0x or 0o
+    #[arg(long = "number-prefix")]
+    number_prefix: bool,
+
+    /// justify line numbers to the right (with a tab after) or left (with
+    /// `: ` after)
+    #[arg(long = "number-align", value_name = "SIDE", value_parser = ["right", "left"])]
+    number_align: Option<String>,
+
+    /// write SEP after line numbers, padding them to the number width
+    #[arg(long = "number-separator", value_name = "SEP")]
+    number_separator: Option<String>,
+
+    /// number only the lines containing PATTERN
+    #[arg(long = "number-matching", value_name = "PATTERN")]
+    number_matching: Option<String>,
+
+    /// start each file on a new line, even if the one before didn't end
+    /// with a newline
+    #[arg(long = "break-between-files")]
+    break_between_files: bool,
+
+    /// write TEXT on a line of its own between files with content
+    #[arg(long = "separator", value_name = "TEXT")]
+    separator: Option<String>,
+
+    /// carry on a line an error cut into after the error, without numbering
+    /// what follows as a new line
+    #[arg(long = "number-blank-as-continuation")]
+    number_blank_as_continuation: bool,
+
+    /// size the number field to fit the last line's number, counting the
+    /// lines of the files first
+    #[arg(long = "number-width-auto")]
+    number_width_auto: bool,
+
+    /// number every line, but write the number only on every Nth one
+    #[arg(long = "number-every", value_name = "N")]
+    number_every: Option<usize>,
+
+    /// carry line numbering on from the number saved in FILE by the last
+    /// run, saving the next one there once done
+    #[arg(long = "number-state", value_name = "FILE")]
+    number_state: Option<String>,
+
+    /// leave the gutter blank for lines past number N
+    #[arg(long = "max-line-number", value_name = "N")]
+    max_line_number: Option<usize>,
+
+    /// with --max-line-number, start again from 1 past N instead
+    #[arg(long = "wrap-line-numbers", requires = "max_line_number")]
+    wrap_line_numbers: bool,
+
+    /// write MARK in place of the number of blank lines
+    #[arg(
+        long = "blank-marker",
+        value_name = "MARK",
+        requires = "show_line_numbers"
+    )]
+    blank_marker: Option<String>,
+
+    /// count the lines containing PATTERN in each file, instead of writing them
+    #[arg(long = "count", value_name = "PATTERN")]
+    count: Option<String>,
+
+    /// treat patterns as regular expressions rather than plain text
+    #[cfg(feature = "regex")]
+    #[arg(long = "regex")]
+    regex: bool,
+
+    /// pad line numbers with spaces up to the next tab stop, rather than a tab
+    #[arg(long = "number-spaces")]
+    number_spaces: bool,
+
+    /// pad line numbers with spaces by display width, rather than a tab
+    #[cfg(feature = "unicode-width")]
+    #[arg(long = "display-width")]
+    display_width: bool,
+
     /// display $ at the end of each line
     #[arg(short = 'E', long = "show-ends")]
     show_line_ends: bool,
+
+    /// display carriage returns as ^M
+    #[arg(long = "show-cr")]
+    show_cr: bool,
+
+    /// drop ANSI escape sequences, like colors
+    #[arg(long = "strip-ansi")]
+    strip_ansi: bool,
+
+    /// fold letters to lower case
+    #[arg(long = "lowercase", conflicts_with = "uppercase")]
+    lowercase: bool,
+
+    /// fold letters to upper case
+    #[arg(long = "uppercase")]
+    uppercase: bool,
+
+    /// fold case over all of Unicode rather than just ASCII
+    #[arg(long = "unicode-case")]
+    unicode_case: bool,
+
+    /// display ␊ at the end of each line, or ␍␊ where it ends with CRLF
+    #[arg(long = "show-line-endings-style")]
+    visible_endings: bool,
+
+    /// prefix each line with the name of the file it came from
+    #[arg(short = 'H', long = "with-filename")]
+    with_filename: bool,
+
+    /// write TEXT before all the content
+    #[arg(long = "prefix", value_name = "TEXT")]
+    prefix: Option<String>,
+
+    /// write TEXT after all the content
+    #[arg(long = "suffix", value_name = "TEXT")]
+    suffix: Option<String>,
+
+    /// write TEXT before the content of each line
+    #[arg(long = "line-prefix", value_name = "TEXT")]
+    line_prefix: Option<String>,
+
+    /// write TEXT after the content of each line
+    #[arg(long = "line-suffix", value_name = "TEXT")]
+    line_suffix: Option<String>,
+
+    /// remove trailing spaces and tabs from each line
+    #[arg(long = "trim-trailing-whitespace")]
+    trim_trailing_whitespace: bool,
+
+    /// limit lines to COLS characters, cutting off the rest
+    #[arg(long = "truncate", value_name = "COLS", conflicts_with = "fold")]
+    truncate: Option<usize>,
+
+    /// limit lines to COLS characters, wrapping the rest onto new lines
+    #[arg(long = "fold", value_name = "COLS")]
+    fold: Option<usize>,
+
+    /// with --fold, break lines after their last space within the limit
+    #[arg(long = "word-wrap", requires = "fold")]
+    word_wrap: bool,
+
+    /// convert tabs to spaces, with tab stops every N columns
+    #[arg(long = "expand-tabs", value_name = "N")]
+    expand_tabs: Option<usize>,
+
+    /// terminate each output line with ENDING instead of a newline
+    #[arg(long = "output-line-ending", value_name = "ENDING", value_parser = ["lf", "crlf", "cr"])]
+    output_line_ending: Option<String>,
+
+    /// end each output line with CRLF or LF, whichever most lines of the
+    /// first file end with
+    #[arg(
+        long = "preserve-dominant-ending",
+        conflicts_with = "output_line_ending"
+    )]
+    preserve_dominant_ending: bool,
+
+    /// character encoding of the input, converted to UTF-8
+    #[arg(long = "input-encoding", value_name = "ENCODING", value_parser = ["utf-8", "latin1"])]
+    input_encoding: Option<String>,
+
+    /// list the files and whether they can be opened, without reading them
+    #[arg(long = "dry-run")]
+    dry_run: bool,
+
+    /// write to FILE instead of standard output, which `-` stands for
+    #[arg(short = 'o', long = "output", value_name = "FILE")]
+    output: Option<String>,
+
+    /// write to standard error instead of standard output
+    #[arg(long = "to-stderr", conflicts_with = "output")]
+    to_stderr: bool,
+
+    /// create missing parent directories of the --output file
+    #[arg(long = "create-dirs", requires = "output")]
+    create_dirs: bool,
+
+    /// write lines longer than this many bytes untransformed, rather than
+    /// hold them whole to trim, fold, reverse, highlight, match or drop them
+    #[arg(long = "max-buffered-line", value_name = "BYTES")]
+    max_buffered_line: Option<usize>,
+
+    /// gather this many bytes of output before writing it out
+    #[arg(long = "output-buffer", value_name = "BYTES")]
+    output_buffer: Option<usize>,
+
+    /// skip files that don't exist without reporting them
+    #[arg(long = "ignore-missing")]
+    ignore_missing: bool,
+
+    /// skip files larger than this many bytes
+    #[arg(long = "max-file-size", value_name = "BYTES")]
+    max_file_size: Option<u64>,
+
+    /// stop once this many bytes have been written, exiting with status 3
+    #[arg(long = "max-total-bytes", value_name = "BYTES")]
+    max_total_bytes: Option<u64>,
+
+    /// write only the last N lines of the output
+    #[arg(long = "tail", value_name = "N", conflicts_with_all = ["join", "join_nul"])]
+    tail: Option<usize>,
+
+    /// give up on standard input or a named pipe after waiting this many
+    /// seconds for something to read
+    #[arg(long = "input-timeout", value_name = "SECS")]
+    input_timeout: Option<f64>,
+
+    /// skip named pipes with nothing to read, instead of waiting on them
+    #[arg(long = "no-block")]
+    no_block: bool,
+
+    /// note each file on standard error as it starts being read
+    #[arg(long = "verbose")]
+    verbose: bool,
+
+    /// write nothing but content, without warnings or errors, which still
+    /// make the exit status nonzero
+    #[arg(short = 'q', long = "quiet", conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// summarize what was read on standard error
+    #[arg(long = "summary")]
+    summary: bool,
+
+    /// write the lines of two files in turn
+    #[arg(long = "interleave")]
+    interleave: bool,
+
+    /// drop blank lines from the start of the output
+    #[arg(long = "squeeze-leading")]
+    squeeze_leading: bool,
+
+    /// drop blank lines from the end of the output
+    #[arg(long = "squeeze-trailing")]
+    squeeze_trailing: bool,
+
+    /// drop each line that's the same as the one before it, like uniq
+    #[arg(long = "unique-adjacent")]
+    unique_adjacent: bool,
+
+    /// end the output with a newline if it doesn't already
+    #[arg(long = "ensure-trailing-newline")]
+    ensure_trailing_newline: bool,
+
+    /// treat input as records ending with NUL rather than lines, numbering
+    /// and ending each one as a line
+    #[arg(short = 'z', long = "zero-terminated")]
+    zero_terminated: bool,
+
+    /// join lines with SEP in place of the newline ending each
+    #[arg(long = "join", value_name = "SEP", conflicts_with = "join_nul")]
+    join: Option<String>,
+
+    /// join lines with NUL in place of the newline ending each
+    #[arg(long = "join-nul")]
+    join_nul: bool,
+
+    /// stop at the first file that can't be read
+    #[arg(long = "strict")]
+    strict: bool,
+
+    /// warn about files that end lines with both LF and CRLF
+    #[arg(long = "warn-mixed-endings")]
+    warn_mixed_endings: bool,
+
+    /// skip files that look binary
+    #[arg(long = "skip-binary")]
+    skip_binary: bool,
+
+    /// output binary files even with --skip-binary
+    #[arg(long = "force")]
+    force: bool,
+
+    /// reverse the characters of each line
+    #[arg(long = "reverse-lines")]
+    reverse_lines: bool,
+
+    /// escape the output as the body of a C string literal
+    #[arg(long = "c-escape")]
+    c_escape: bool,
+
+    /// base64 encode the output
+    #[cfg(feature = "base64")]
+    #[arg(long = "base64", conflicts_with = "base64_decode")]
+    base64_encode: bool,
+
+    /// wrap encoded lines after COLS characters (0 disables wrapping)
+    #[cfg(feature = "base64")]
+    #[arg(long = "wrap", value_name = "COLS", requires = "base64_encode")]
+    base64_wrap: Option<usize>,
+
+    /// decode base64 input
+    #[cfg(feature = "base64")]
+    #[arg(long = "base64-decode")]
+    base64_decode: bool,
+
+    /// write each line as a JSON object
+    #[cfg(feature = "serde_json")]
+    #[arg(long = "ndjson")]
+    ndjson: bool,
+
+    /// cat the regular files under each FILE that's a directory, in sorted
+    /// order, without following symlinks inside it
+    #[arg(short = 'r', long = "recursive")]
+    recursive: bool,
+
+    /// cat the regular files inside each FILE, a tar archive
+    #[cfg(feature = "tar")]
+    #[arg(long = "tar")]
+    tar: bool,
+
+    /// cat the text on the clipboard after any FILE
+    #[cfg(feature = "arboard")]
+    #[arg(long = "clipboard")]
+    clipboard: bool,
+
+    /// write the lines as a single JSON array of objects
+    #[cfg(feature = "serde_json")]
+    #[arg(long = "json", conflicts_with = "ndjson")]
+    json: bool,
+
+    /// highlight the syntax of source files when writing to a terminal
+    #[cfg(feature = "syntect")]
+    #[arg(long = "highlight")]
+    highlight: bool,
+
+    /// print a checksum of the output to standard error
+    #[cfg(feature = "checksum")]
+    #[arg(long = "checksum", value_name = "ALGO", value_parser = ["sha256", "crc32"])]
+    checksum: Option<String>,
+
+    /// compress the output with gzip
+    #[cfg(feature = "flate2")]
+    #[arg(long = "gzip")]
+    gzip: bool,
 }
 
 // Please note that is a simplified version of the linux `cat` command.
-// It supports only two flags:
-// 1. `-n` or `--number` to number all output lines
-// 2. `-E` or `--show-ends` to display $ at the end of each line
+// See README.md for the flags it supports.
 //
 // It correctly supports standard input using the `-` character or
-// when no files are specified.
+// when no files are specified. Standard input is only read once, so
+// any further `-` is skipped with a warning.
 //
 // It doesn't innately support wildcards. However, if the system/shell
 // automatically expands wildcards before passing them to the executable,
@@ -40,26 +408,512 @@ struct Args {
 fn main() -> std::io::Result<()> {
     let args = Args::parse();
 
-    let mut inputs = args
-        .file
-        .into_iter()
-        .map(|f| match f.as_str() {
-            "-" => InputSource::StdIn,
-            _ => InputSource::File(f),
-        })
-        .collect::<Vec<_>>();
+    let mut inputs = match &args.files0_from {
+        Some(list) => files0_from(list).unwrap_or_else(|e| {
+            eprintln!("cat: {e}");
+            process::exit(1);
+        }),
+        None => args
+            .file
+            .iter()
+            .map(InputSource::from_name)
+            .collect::<Vec<_>>(),
+    };
+
+    // Directories are walked for the files under them
+    if args.recursive {
+        inputs = inputs
+            .into_iter()
+            .flat_map(|input| match input {
+                InputSource::File(dir) if Path::new(&dir).is_dir() => InputSource::dir_files(&dir)
+                    .unwrap_or_else(|e| {
+                        eprintln!("cat: {e}");
+                        process::exit(1);
+                    }),
+                input => vec![input],
+            })
+            .collect();
+    }
+
+    // Each file is a tar archive, whose members are catted in its place
+    #[cfg(feature = "tar")]
+    if args.tar {
+        inputs = inputs
+            .into_iter()
+            .flat_map(|input| match input {
+                InputSource::File(archive) => {
+                    InputSource::tar_members(&archive).unwrap_or_else(|e| {
+                        eprintln!("cat: {archive}: {e}");
+                        process::exit(1);
+                    })
+                }
+                input => vec![input],
+            })
+            .collect();
+    }
+
+    #[cfg(feature = "arboard")]
+    if args.clipboard {
+        inputs.push(InputSource::clipboard());
+    }
 
+    // Reading standard input when there are no files is up to us, not the
+    // library
     if inputs.is_empty() {
         inputs.push(InputSource::StdIn);
     }
 
+    let output = args.output.clone();
+    let catter = concatenator(args, inputs);
+    let result = write_output(catter, output.as_deref());
+    #[cfg(feature = "checksum")]
+    if let Ok(CatStats {
+        checksum: Some(digest),
+        ..
+    }) = &result
+    {
+        eprintln!("{digest}  -");
+    }
+    // Failed inputs were already reported along with the output
+    match result {
+        Ok(stats) if stats.output_limit_reached => process::exit(3),
+        Ok(stats) if stats.errors.is_empty() => Ok(()),
+        Ok(_) => process::exit(1),
+        Err(e) => {
+            eprintln!("cat: {e}");
+            process::exit(1);
+        }
+    }
+}
+
+// Sets up the catting of `inputs` the way the flags ask
+fn concatenator(args: Args, inputs: Vec<InputSource>) -> Concatenator {
     let mut catter = Concatenator::new(inputs);
     if args.show_line_numbers {
         catter = catter.with_line_numbers();
     }
+    if let Some(format) = args.timestamp {
+        catter = catter.with_timestamp_prefix(format);
+    }
+    if args.source_offsets {
+        catter = catter.with_source_byte_gutter();
+    }
+    if args.byte_offsets {
+        catter = catter.with_byte_offsets();
+    }
+    #[cfg(feature = "regex")]
+    let regex = args.regex;
+    #[cfg(not(feature = "regex"))]
+    let regex = false;
+    if let Some(pattern) = &args.number_matching {
+        catter = catter.with_number_matching(line_pattern(pattern, regex));
+    }
+    if args.break_between_files {
+        catter = catter.with_force_line_break_between_files();
+    }
+    if let Some(separator) = args.separator {
+        catter = catter.with_file_separator(separator);
+    }
+    if args.number_blank_as_continuation {
+        catter = catter.with_number_blank_as_continuation();
+    }
+    if args.number_width_auto {
+        catter = catter.with_auto_number_width();
+    }
+    if let Some(n) = args.number_every {
+        catter = catter.with_line_number_every(n);
+    }
+    if let Some(state) = args.number_state {
+        catter = catter.with_number_state_file(state);
+    }
+    if let Some(max) = args.max_line_number {
+        let mode = match args.wrap_line_numbers {
+            true => CapMode::Wrap,
+            false => CapMode::Stop,
+        };
+        catter = catter.with_line_number_cap(max, mode);
+    }
+    if let Some(marker) = args.blank_marker {
+        catter = catter.with_blank_line_marker(marker);
+    }
+    if let Some(pattern) = &args.count {
+        catter = catter.with_grep_count(line_pattern(pattern, regex));
+    }
+    match args.number_format.as_deref() {
+        Some("hex") => catter = catter.with_number_format(NumberFormat::Hex, args.number_prefix),
+        Some("oct") => catter = catter.with_number_format(NumberFormat::Oct, args.number_prefix),
+        _ => {}
+    }
+    let left_align = args.number_align.as_deref() == Some("left");
+    match (args.number_separator, left_align) {
+        (Some(separator), left_align) => {
+            catter = catter.with_number_style(NumberStyle::Custom {
+                separator,
+                left_align,
+            })
+        }
+        (None, true) => catter = catter.with_number_style(NumberStyle::LeftColon),
+        (None, false) => {}
+    }
+    if args.number_spaces {
+        catter = catter.with_replace_tabs_in_numbering();
+    }
+    #[cfg(feature = "unicode-width")]
+    if args.display_width {
+        catter = catter.with_display_width_numbering();
+    }
+    if args.show_cr {
+        catter = catter.with_show_carriage_returns();
+    }
+    if args.strip_ansi {
+        catter = catter.with_strip_ansi();
+    }
+    if args.lowercase {
+        catter = catter.with_lowercase();
+    }
+    if args.uppercase {
+        catter = catter.with_uppercase();
+    }
+    if args.unicode_case {
+        catter = catter.with_unicode_case();
+    }
+    if args.visible_endings {
+        catter = catter.with_visible_line_endings();
+    }
     if args.show_line_ends {
         catter = catter.with_line_endings();
     }
-    catter.concatenate()?;
-    Ok(())
+    if args.with_filename {
+        catter = catter.with_filename_prefix();
+    }
+    if let Some(prefix) = args.prefix {
+        catter = catter.with_prefix(prefix);
+    }
+    if let Some(suffix) = args.suffix {
+        catter = catter.with_suffix(suffix);
+    }
+    if let Some(line_prefix) = args.line_prefix {
+        catter = catter.with_line_prefix(line_prefix);
+    }
+    if let Some(line_suffix) = args.line_suffix {
+        catter = catter.with_line_suffix(line_suffix);
+    }
+    if args.trim_trailing_whitespace {
+        catter = catter.with_trim_trailing_whitespace();
+    }
+    if let Some(cols) = args.truncate {
+        catter = catter.with_max_line_width(cols, WrapMode::Truncate { ellipsis: true });
+    }
+    if let Some(cols) = args.fold {
+        let mode = match args.word_wrap {
+            true => WrapMode::Word,
+            false => WrapMode::Wrap,
+        };
+        catter = catter.with_max_line_width(cols, mode);
+    }
+    if let Some(tabstop) = args.expand_tabs {
+        catter = catter.with_expand_tabs(tabstop);
+    }
+    match args.output_line_ending.as_deref() {
+        Some("crlf") => catter = catter.with_output_line_ending(LineEnding::CrLf),
+        Some("cr") => catter = catter.with_output_line_ending(LineEnding::Cr),
+        _ => {}
+    }
+    if args.preserve_dominant_ending {
+        catter = catter.with_preserve_dominant_ending();
+    }
+    if args.input_encoding.as_deref() == Some("latin1") {
+        catter = catter.with_input_encoding(Encoding::Latin1);
+    }
+    if args.create_dirs {
+        catter = catter.with_create_dirs();
+    }
+    if args.dry_run {
+        catter = catter.with_dry_run();
+    }
+    if let Some(bytes) = args.max_buffered_line {
+        catter = catter.with_max_buffered_line(bytes);
+    }
+    if let Some(bytes) = args.output_buffer {
+        catter = catter.with_output_buffer_capacity(bytes);
+    }
+    if args.ignore_missing {
+        catter = catter.with_ignore_missing();
+    }
+    if let Some(bytes) = args.max_file_size {
+        catter = catter.with_max_file_size(bytes);
+    }
+    if let Some(n) = args.tail {
+        catter = catter.with_tail_lines(n);
+    }
+    if let Some(bytes) = args.max_total_bytes {
+        catter = catter.with_max_total_bytes(bytes);
+    }
+    if let Some(secs) = args.input_timeout {
+        catter = catter.with_input_timeout(Duration::from_secs_f64(secs));
+    }
+    if args.no_block {
+        catter = catter.with_no_block();
+    }
+    if args.verbose {
+        catter = catter.with_verbose();
+    }
+    if args.quiet {
+        catter = catter.with_quiet();
+    }
+    if args.to_stderr {
+        catter = catter.with_output_stderr();
+    }
+    if args.summary {
+        catter = catter.with_summary();
+    }
+    if args.interleave {
+        catter = catter.with_interleave();
+    }
+    if args.squeeze_leading || args.squeeze_trailing {
+        catter = catter.with_trim_blank_edges(args.squeeze_leading, args.squeeze_trailing);
+    }
+    if args.unique_adjacent {
+        catter = catter.with_unique_adjacent();
+    }
+    if args.zero_terminated {
+        catter = catter.with_record_separator(b'\0');
+    }
+    if let Some(separator) = args.join {
+        catter = catter.with_replace_newlines(separator.into_bytes());
+    }
+    if args.join_nul {
+        catter = catter.with_replace_newlines(vec![b'\0']);
+    }
+    if args.ensure_trailing_newline {
+        catter = catter.with_ensure_trailing_newline();
+    }
+    if args.strict {
+        catter = catter.with_strict();
+    }
+    if args.warn_mixed_endings {
+        catter = catter.with_warn_mixed_endings();
+    }
+    if args.skip_binary {
+        catter = catter.with_skip_binary();
+    }
+    if args.force {
+        catter = catter.with_force();
+    }
+    if args.reverse_lines {
+        catter = catter.with_reverse_lines();
+    }
+    if args.c_escape {
+        catter = catter.with_c_escape();
+    }
+    #[cfg(feature = "base64")]
+    if args.base64_encode {
+        catter = catter.with_base64_encode(args.base64_wrap);
+    }
+    #[cfg(feature = "base64")]
+    if args.base64_decode {
+        catter = catter.with_base64_decode();
+    }
+    #[cfg(feature = "serde_json")]
+    if args.ndjson {
+        catter = catter.with_ndjson();
+    }
+    #[cfg(feature = "serde_json")]
+    if args.json {
+        catter = catter.with_json();
+    }
+    #[cfg(feature = "syntect")]
+    if args.highlight {
+        catter = catter.with_syntax_highlight();
+    }
+    #[cfg(feature = "checksum")]
+    match args.checksum.as_deref() {
+        Some("sha256") => catter = catter.with_checksum(ChecksumAlgo::Sha256),
+        Some("crc32") => catter = catter.with_checksum(ChecksumAlgo::Crc32),
+        _ => {}
+    }
+    #[cfg(feature = "flate2")]
+    if args.gzip {
+        catter = catter.with_gzip_output();
+    }
+    catter
+}
+
+// Writes to the file at `output`, or to standard output when there's none
+// or it's `-`
+fn write_output(catter: Concatenator, output: Option<&str>) -> io::Result<CatStats> {
+    match output {
+        None | Some("-") => catter.concatenate(),
+        Some(path) => catter.write_to_path(path),
+    }
+}
+
+// Pattern for matching lines, exiting if it isn't a valid regex
+#[cfg_attr(not(feature = "regex"), allow(unused_variables))]
+fn line_pattern(pattern: &str, regex: bool) -> LinePattern {
+    #[cfg(feature = "regex")]
+    if regex {
+        return regex::bytes::Regex::new(pattern)
+            .map(LinePattern::Regex)
+            .unwrap_or_else(|e| {
+                eprintln!("cat: {e}");
+                process::exit(1);
+            });
+    }
+    LinePattern::Substring(pattern.to_string())
+}
+
+// Reads the input files listed in `list` for --files0-from
+fn files0_from(list: &str) -> Result<Vec<InputSource>, String> {
+    let names = match list {
+        "-" => parse_files0(io::stdin(), list),
+        _ => parse_files0(
+            fs::File::open(list).map_err(|e| format!("{list}: {e}"))?,
+            list,
+        ),
+    };
+    names.map(|names| names.into_iter().map(InputSource::from_name).collect())
+}
+
+// Splits a NUL-separated list of file names. The list may or may not end
+// with a NUL, but names in it can't be empty, nor `-` when the list itself
+// was read from standard input.
+fn parse_files0(mut list: impl Read, list_name: &str) -> Result<Vec<String>, String> {
+    let mut bytes = Vec::new();
+    list.read_to_end(&mut bytes)
+        .map_err(|e| format!("{list_name}: {e}"))?;
+    if bytes.last() == Some(&0) {
+        bytes.pop();
+    }
+    if bytes.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    bytes
+        .split(|b| *b == 0)
+        .enumerate()
+        .map(|(i, name)| match name {
+            [] => Err(format!(
+                "{list_name}:{}: invalid zero-length file name",
+                i + 1
+            )),
+            b"-" if list_name == "-" => {
+                Err("when reading file names from stdin, no file name of '-' allowed".to_string())
+            }
+            _ => Ok(String::from_utf8_lossy(name).into_owned()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test_dir;
+
+#[cfg(test)]
+mod main_tests {
+    use std::{fs, io::Cursor};
+
+    use super::{concatenator, files0_from, parse_files0, test_dir::TempDir, write_output, Args};
+    use cat::InputSource;
+    use clap::Parser;
+
+    #[test]
+    fn files0_list_in_order() {
+        let dir = TempDir::new("files0").unwrap();
+        let paths = ["b.txt", "a.txt", "c d.txt"].map(|name| {
+            let path = dir.join(name).to_string_lossy().into_owned();
+            fs::write(&path, name).unwrap();
+            path
+        });
+        let list = dir.join("list");
+        fs::write(&list, format!("{}\0{}\0{}\0", paths[0], paths[1], paths[2])).unwrap();
+
+        let inputs = files0_from(list.to_str().unwrap()).unwrap();
+
+        assert_eq!(
+            inputs,
+            paths.map(InputSource::File).into_iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn files0_dash_is_stdin() {
+        let dir = TempDir::new("files0-dash").unwrap();
+        let list = dir.join("list");
+        fs::write(&list, "a.txt\0-\0b.txt\0").unwrap();
+
+        let inputs = files0_from(list.to_str().unwrap()).unwrap();
+
+        assert_eq!(
+            inputs,
+            [
+                InputSource::File("a.txt".to_string()),
+                InputSource::StdIn,
+                InputSource::File("b.txt".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn output_flag_writes_file() {
+        let dir = TempDir::new("output").unwrap();
+        let input = dir.join("in.txt").to_string_lossy().into_owned();
+        let output = dir.join("out.txt").to_string_lossy().into_owned();
+        fs::write(&input, "one\ntwo\n").unwrap();
+
+        let args = Args::try_parse_from(["cat", "-n", "-o", &output, &input]).unwrap();
+        let inputs = args.file.iter().map(InputSource::from_name).collect();
+        let output_path = args.output.clone();
+        let stats = write_output(concatenator(args, inputs), output_path.as_deref()).unwrap();
+        let written = fs::read_to_string(&output).unwrap();
+
+        // The target can't be opened when its directory doesn't exist
+        let missing = dir.join("missing").join("out.txt");
+        let args = Args::try_parse_from(["cat", "-o", missing.to_str().unwrap(), &input]).unwrap();
+        let result = write_output(concatenator(args, vec![]), missing.to_str());
+
+        assert_eq!(written, "     1\tone\n     2\ttwo\n");
+        assert!(stats.errors.is_empty());
+        assert!(result.is_err_and(|e| e.to_string().starts_with(missing.to_str().unwrap())));
+    }
+
+    #[test]
+    fn directory_without_recursive_reported() {
+        let dir = TempDir::new("dir-arg").unwrap();
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        let file = dir.join("f.txt").to_string_lossy().into_owned();
+        let sub = dir.join("sub").to_string_lossy().into_owned();
+        let output = dir.join("out.txt").to_string_lossy().into_owned();
+        fs::write(&file, "f\n").unwrap();
+
+        let args = Args::try_parse_from(["cat", "-q", "-o", &output, &file, &sub, &file]).unwrap();
+        let inputs = args.file.iter().map(InputSource::from_name).collect();
+        let stats = write_output(concatenator(args, inputs), Some(&output)).unwrap();
+        let written = fs::read_to_string(&output).unwrap();
+
+        assert_eq!(written, "f\nf\n");
+        assert_eq!(stats.errors.len(), 1);
+        assert!(stats.errors[0].message.starts_with(&sub));
+        assert!(stats.errors[0].message.contains("Is a directory"));
+    }
+
+    #[test]
+    fn files0_empty_name_error() {
+        assert_eq!(
+            parse_files0(Cursor::new("a.txt\0\0b.txt"), "list"),
+            Err("list:2: invalid zero-length file name".to_string())
+        );
+        assert_eq!(
+            parse_files0(Cursor::new("a.txt\0b.txt"), "list"),
+            Ok(vec!["a.txt".to_string(), "b.txt".to_string()])
+        );
+        assert_eq!(parse_files0(Cursor::new(""), "list"), Ok(vec![]));
+    }
+
+    #[test]
+    fn files0_dash_from_stdin_error() {
+        assert_eq!(
+            parse_files0(Cursor::new("a.txt\0-\0"), "-"),
+            Err("when reading file names from stdin, no file name of '-' allowed".to_string())
+        );
+    }
 }